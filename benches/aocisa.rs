@@ -0,0 +1,40 @@
+// Compares how many instructions per second the interpreter and the compiled machine run. Run
+// with cargo bench.
+extern crate aoclib;
+
+use aoclib::aocisa::*;
+use std::time::Instant;
+
+// Counts a down from 200 twenty thousand times, summing into b, which is about 12 million steps.
+const PROGRAM : &str =
+r"set c 20000
+set a 200
+add b a
+sub a 1
+jgz a -2
+sub c 1
+jgz c -5";
+
+fn measure<F>(name : &str, run : F)
+where F : FnOnce() -> (u64, i64) {
+    let start = Instant::now();
+    let (steps, result) = run();
+    let seconds = start.elapsed().as_secs_f64();
+    println!("{:<12} {:>10} steps in {:>8.3}s, {:>8.1}M steps/s (b = {})", name, steps, seconds, steps as f64 / seconds / 1e6, result);
+}
+
+fn main() {
+    let program = Program::load(PROGRAM);
+
+    measure("interpreted", || {
+        let mut machine = Machine::new(program.clone());
+        machine.run();
+        (machine.steps(), *machine.registers().get_reg('b'))
+    });
+
+    measure("compiled", || {
+        let mut machine = CompiledMachine::new(program.clone());
+        machine.run();
+        (machine.steps(), machine.get_reg('b').unwrap())
+    });
+}
@@ -0,0 +1,238 @@
+use super::*;
+
+// When a conditional jump is taken.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Condition {
+    NonZero(RegisterOrValue),
+    Positive(RegisterOrValue),
+}
+
+// A simplified, instruction set independent view of what an instruction does, so that analysis
+// passes can look at programs without knowing every opcode.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Op {
+    Set(char, RegisterOrValue),
+    Add(char, RegisterOrValue),
+    Sub(char, RegisterOrValue),
+    Mul(char, RegisterOrValue),
+    Mod(char, RegisterOrValue),
+    Jump(Condition, RegisterOrValue),
+    Send(RegisterOrValue),
+    Receive(char),
+    Toggle(RegisterOrValue),
+    Nop,
+}
+
+// Instruction sets that can describe their instructions as Ops.
+pub trait Analyze {
+    fn op(&self) -> Op;
+}
+
+// A run of instructions that is only ever entered at the start and only ever left at the end.
+// end is exclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BasicBlock {
+    pub start : usize,
+    pub end : usize,
+}
+
+// A backward jump with a constant offset. head is the jump's target and latch is the jump itself,
+// so the loop's body is head .. latch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Loop {
+    pub head : usize,
+    pub latch : usize,
+}
+
+impl Condition {
+    // Whether the condition always or never holds, if that's known without running anything.
+    pub fn constant(&self) -> Option<bool> {
+        match *self {
+            Condition::NonZero(RegisterOrValue::Val(v)) => Some(v != 0),
+            Condition::Positive(RegisterOrValue::Val(v)) => Some(v > 0),
+            _ => None,
+        }
+    }
+
+    pub fn operand(&self) -> &RegisterOrValue {
+        match *self {
+            Condition::NonZero(ref rv) |
+            Condition::Positive(ref rv) => rv,
+        }
+    }
+}
+
+impl Op {
+    // Where a jump at the index can go, if its offset is a constant. Targets before the start of
+    // the program come out as usize::MAX, like offset_ip.
+    pub fn jump_target(&self, index : usize) -> Option<usize> {
+        match *self {
+            Op::Jump(ref cond, RegisterOrValue::Val(offset)) if cond.constant() != Some(false) => {
+                Some(offset_ip(index, offset))
+            },
+            _ => None,
+        }
+    }
+
+    // Whether execution can continue with the next instruction.
+    pub fn falls_through(&self) -> bool {
+        match *self {
+            Op::Jump(ref cond, _) => cond.constant() != Some(true),
+            _ => true,
+        }
+    }
+}
+
+impl Analyze for Instruction {
+    fn op(&self) -> Op {
+        match self.clone() {
+            Instruction::Snd(rv) => Op::Send(rv),
+            Instruction::Set(reg, rv) => Op::Set(reg, rv),
+            Instruction::Add(reg, rv) => Op::Add(reg, rv),
+            Instruction::Sub(reg, rv) => Op::Sub(reg, rv),
+            Instruction::Mul(reg, rv) => Op::Mul(reg, rv),
+            Instruction::Mod(reg, rv) => Op::Mod(reg, rv),
+            Instruction::Rcv(reg) => Op::Receive(reg),
+            Instruction::Jgz(cond, offset) => Op::Jump(Condition::Positive(cond), offset),
+            Instruction::Jnz(cond, offset) => Op::Jump(Condition::NonZero(cond), offset),
+        }
+    }
+}
+
+impl Analyze for Assembunny {
+    fn op(&self) -> Op {
+        match self.clone() {
+            Assembunny::Cpy(src, RegisterOrValue::Reg(reg)) => Op::Set(reg, src),
+            Assembunny::Inc(RegisterOrValue::Reg(reg)) => Op::Add(reg, RegisterOrValue::Val(1)),
+            Assembunny::Dec(RegisterOrValue::Reg(reg)) => Op::Sub(reg, RegisterOrValue::Val(1)),
+            Assembunny::Jnz(cond, offset) => Op::Jump(Condition::NonZero(cond), offset),
+            Assembunny::Tgl(offset) => Op::Toggle(offset),
+            Assembunny::Out(rv) => Op::Send(rv),
+            Assembunny::Cpy(..) |
+            Assembunny::Inc(..) |
+            Assembunny::Dec(..) => Op::Nop,
+        }
+    }
+}
+
+// Splits the program into basic blocks. A block starts at the beginning of the program, at every
+// constant jump target, and after every jump. Jumps with a register offset could go anywhere, so
+// blocks are only as precise as the constant jumps allow.
+pub fn basic_blocks<I>(program : &Program<I>) -> Vec<BasicBlock>
+where I : Analyze {
+    let len = program.instructions.len();
+    let mut leaders = vec![false ; len + 1];
+    leaders[0] = true;
+    leaders[len] = true;
+
+    for (i, instruction) in program.instructions.iter().enumerate() {
+        let op = instruction.op();
+        if let Op::Jump(..) = op {
+            leaders[i + 1] = true;
+        }
+
+        if let Some(target) = op.jump_target(i) {
+            if target < len {
+                leaders[target] = true;
+            }
+        }
+    }
+
+    let starts : Vec<usize> = (0 ..= len).filter(|i| leaders[*i]).collect();
+    starts.windows(2).map(|w| BasicBlock { start : w[0], end : w[1] }).collect()
+}
+
+// Every backward jump with a constant offset, ordered by where the jump is.
+pub fn find_loops<I>(program : &Program<I>) -> Vec<Loop>
+where I : Analyze {
+    program.instructions.iter().enumerate().filter_map(|(latch, instruction)| {
+        instruction.op().jump_target(latch).and_then(|head| {
+            if head <= latch {
+                Some(Loop { head, latch })
+            } else {
+                None
+            }
+        })
+    }).collect()
+}
+
+// The loops that don't contain any other loop.
+pub fn inner_loops<I>(program : &Program<I>) -> Vec<Loop>
+where I : Analyze {
+    let loops = find_loops(program);
+    loops.iter().filter(|outer| {
+        !loops.iter().any(|inner| inner != *outer && outer.contains(inner))
+    }).cloned().collect()
+}
+
+impl BasicBlock {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl Loop {
+    pub fn len(&self) -> usize {
+        self.latch - self.head + 1
+    }
+
+    // A loop always includes at least its latch, so this is only true for one built by hand with
+    // the latch before the head.
+    pub fn is_empty(&self) -> bool {
+        self.latch < self.head
+    }
+
+    pub fn contains(&self, other : &Loop) -> bool {
+        self.head <= other.head && other.latch <= self.latch
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn multiply() -> Program<Assembunny> {
+        r"cpy 7 a
+cpy 5 b
+cpy 3 d
+cpy b c
+inc a
+dec c
+jnz c -2
+dec d
+jnz d -5".parse().unwrap()
+    }
+
+    #[test]
+    fn ops() {
+        assert_eq!(Instruction::from("jgz a -2").op(), Op::Jump(Condition::Positive(RegisterOrValue::Reg('a')), RegisterOrValue::Val(-2)));
+        assert_eq!("dec b".parse::<Assembunny>().unwrap().op(), Op::Sub('b', RegisterOrValue::Val(1)));
+        assert_eq!("cpy a 3".parse::<Assembunny>().unwrap().op(), Op::Nop);
+    }
+
+    #[test]
+    fn blocks() {
+        let starts : Vec<(usize, usize)> = basic_blocks(&multiply()).iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(starts, vec![(0, 3), (3, 4), (4, 7), (7, 9)]);
+
+        // A jump with a register offset still ends a block, and an unconditional jump's target
+        // starts one.
+        let program = Program::load("set a 1\njgz a b\nset b 2\njgz 1 -1\nsnd b");
+        let starts : Vec<(usize, usize)> = basic_blocks(&program).iter().map(|b| (b.start, b.end)).collect();
+        assert_eq!(starts, vec![(0, 2), (2, 4), (4, 5)]);
+    }
+
+    #[test]
+    fn loops() {
+        let program = multiply();
+        assert_eq!(find_loops(&program), vec![Loop { head : 4, latch : 6 }, Loop { head : 3, latch : 8 }]);
+        assert_eq!(inner_loops(&program), vec![Loop { head : 4, latch : 6 }]);
+        assert_eq!(Loop { head : 4, latch : 6 }.len(), 3);
+        assert!(!Loop { head : 4, latch : 4 }.is_empty());
+        assert!(Loop { head : 4, latch : 3 }.is_empty());
+    }
+}
@@ -0,0 +1,461 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+// How deeply macros can use other macros before the assembler assumes they recurse forever.
+const MAX_MACRO_DEPTH : usize = 16;
+
+const NAME_FORM : &str = "a name that isn't a register, like loop_top";
+
+lazy_static! {
+    static ref RE_NAME : regex::Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("failed to compile regex");
+}
+
+// A program assembled from source, along with where each of its instructions came from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Assembly<I = Instruction> {
+    pub program : Program<I>,
+
+    // The 1-based source line of each instruction. Instructions from a macro map to the line
+    // that used the macro.
+    pub source_map : Vec<usize>,
+
+    // The instruction index of each label. Labels inside macros don't appear.
+    pub labels : BTreeMap<String, usize>,
+}
+
+// The tokens of a line, with their columns, plus the line they came from and the line that ends
+// up in the source map, which differs for lines expanded from a macro. Labels on expanded lines
+// have a suffix after a '.'.
+#[derive(Clone, Debug)]
+struct SourceLine {
+    line : usize,
+    origin : usize,
+    tokens : Vec<(usize, String)>,
+    expanded : bool,
+}
+
+struct Macro {
+    params : Vec<String>,
+    body : Vec<SourceLine>,
+}
+
+#[derive(Default)]
+struct Assembler {
+    constants : HashMap<String, i64>,
+    macros : HashMap<String, Macro>,
+    lines : Vec<SourceLine>,
+    expansions : usize,
+    errors : Vec<ParseError>,
+}
+
+fn error(line : usize, token : &(usize, String), expected : &str) -> ParseError {
+    ParseError::new(token.0, &token.1, &[expected]).at_line(line)
+}
+
+// An empty token just past the last one, for errors about something missing.
+fn end_of(tokens : &[(usize, String)]) -> (usize, String) {
+    let last = tokens.last().unwrap();
+    (last.0 + last.1.chars().count(), String::new())
+}
+
+fn is_label(token : &(usize, String)) -> bool {
+    token.1.len() > 1 && token.1.ends_with(':')
+}
+
+fn is_valid_name(name : &str) -> bool {
+    RE_NAME.is_match(name) && !RE_REGISTER.is_match(name)
+}
+
+impl SourceLine {
+    fn new(line : usize, text : &str) -> SourceLine {
+        let code = match text.find('#') {
+            Some(comment) => &text[.. comment],
+            None => text,
+        };
+
+        SourceLine {
+            line,
+            origin : line,
+            tokens : tokenize(code).iter().map(|t| (t.column, String::from(t.text))).collect(),
+            expanded : false,
+        }
+    }
+
+    fn label_count(&self) -> usize {
+        self.tokens.iter().take_while(|t| is_label(t)).count()
+    }
+}
+
+impl Assembler {
+    fn read(&mut self, source : &str) {
+        let mut lines = source.lines().enumerate().map(|(i, text)| SourceLine::new(i + 1, text));
+        while let Some(line) = lines.next() {
+            let first = match line.tokens.first() {
+                Some(first) => first.clone(),
+                None => continue,
+            };
+
+            match first.1.as_str() {
+                ".const" => self.define_constant(&line),
+                ".macro" => {
+                    let mut body = vec![];
+                    let mut closed = false;
+                    for body_line in &mut lines {
+                        match body_line.tokens.first().map(|t| t.1.as_str()) {
+                            Some(".endm") => {
+                                closed = true;
+                                break;
+                            },
+                            Some(".macro") => self.errors.push(error(body_line.line, &body_line.tokens[0], "a line inside a macro")),
+                            _ => body.push(body_line),
+                        }
+                    }
+
+                    if closed {
+                        self.define_macro(&line, body);
+                    } else {
+                        self.errors.push(ParseError::new(1, "", &[".endm"]).at_line(line.line));
+                    }
+                },
+                text if text.starts_with('.') => {
+                    self.errors.push(error(line.line, &first, "one of: .const, .macro, .endm, or an instruction"));
+                },
+                _ => self.emit(line, 0),
+            }
+        }
+    }
+
+    fn define_constant(&mut self, line : &SourceLine) {
+        let tokens = &line.tokens;
+        if tokens.len() != 3 {
+            let token = tokens.get(3).cloned().unwrap_or_else(|| end_of(&line.tokens));
+            self.errors.push(error(line.line, &token, ".const <name> <value>"));
+            return;
+        }
+
+        if !is_valid_name(&tokens[1].1) || self.constants.contains_key(&tokens[1].1) {
+            self.errors.push(error(line.line, &tokens[1], NAME_FORM));
+            return;
+        }
+
+        let value = match tokens[2].1.parse::<i64>() {
+            Ok(value) => value,
+            Err(_) => match self.constants.get(&tokens[2].1) {
+                Some(value) => *value,
+                None => {
+                    self.errors.push(error(line.line, &tokens[2], "<value>"));
+                    return;
+                },
+            },
+        };
+
+        self.constants.insert(tokens[1].1.clone(), value);
+    }
+
+    fn define_macro(&mut self, line : &SourceLine, body : Vec<SourceLine>) {
+        let name = match line.tokens.get(1) {
+            Some(name) => name,
+            None => {
+                self.errors.push(ParseError::new(line.tokens[0].0 + 6, "", &[".macro <name> <params>"]).at_line(line.line));
+                return;
+            },
+        };
+
+        // Parameters can look like registers, since they often stand in for one.
+        for (i, token) in line.tokens[1 ..].iter().enumerate() {
+            if !RE_NAME.is_match(&token.1) || (i == 0 && !is_valid_name(&token.1)) {
+                self.errors.push(error(line.line, token, NAME_FORM));
+                return;
+            }
+        }
+
+        self.macros.insert(name.1.clone(), Macro {
+            params : line.tokens[2 ..].iter().map(|t| t.1.clone()).collect(),
+            body,
+        });
+    }
+
+    // Adds a line to the program, expanding it if it uses a macro.
+    fn emit(&mut self, line : SourceLine, depth : usize) {
+        let labels = line.label_count();
+        let macros = &self.macros;
+        let invocation = match line.tokens.get(labels).and_then(|t| macros.get(&t.1)) {
+            Some(invoked) => {
+                let args = &line.tokens[labels + 1 ..];
+                if args.len() != invoked.params.len() {
+                    let token = args.get(invoked.params.len()).cloned().unwrap_or_else(|| end_of(&line.tokens));
+                    let expected = format!("{} arguments to {}", invoked.params.len(), line.tokens[labels].1);
+                    self.errors.push(error(line.line, &token, &expected));
+                    return;
+                }
+
+                // Labels defined inside the macro get a suffix so that each use has its own.
+                self.expansions += 1;
+                let mut renames : HashMap<String, String> = invoked.params.iter().cloned().zip(args.iter().map(|a| a.1.clone())).collect();
+                for body_line in &invoked.body {
+                    for label in body_line.tokens.iter().take_while(|t| is_label(t)) {
+                        let name = label.1.trim_end_matches(':');
+                        renames.insert(String::from(name), format!("{}.{}", name, self.expansions));
+                    }
+                }
+
+                let body : Vec<SourceLine> = invoked.body.iter().map(|body_line| {
+                    SourceLine {
+                        line : body_line.line,
+                        origin : line.origin,
+                        tokens : body_line.tokens.iter().map(|token| {
+                            let name = token.1.trim_end_matches(':');
+                            let suffix = &token.1[name.len() ..];
+                            (token.0, format!("{}{}", renames.get(name).map(|s| s.as_str()).unwrap_or(name), suffix))
+                        }).collect(),
+                        expanded : true,
+                    }
+                }).collect();
+
+                Some(body)
+            },
+            None => None,
+        };
+
+        match invocation {
+            Some(body) => {
+                if depth >= MAX_MACRO_DEPTH {
+                    self.errors.push(error(line.line, &line.tokens[labels], "a macro that doesn't use itself"));
+                    return;
+                }
+
+                if labels > 0 {
+                    self.lines.push(SourceLine {
+                        line : line.line,
+                        origin : line.origin,
+                        tokens : line.tokens[.. labels].to_vec(),
+                        expanded : line.expanded,
+                    });
+                }
+
+                for body_line in body {
+                    self.emit(body_line, depth + 1);
+                }
+            },
+            None => self.lines.push(line),
+        }
+    }
+
+    fn assemble<I>(mut self) -> Result<Assembly<I>, Vec<ParseError>>
+    where I : InstructionSet {
+        let mut labels = BTreeMap::new();
+        let mut index = 0;
+        for line in &self.lines {
+            let count = line.label_count();
+            for token in &line.tokens[.. count] {
+                // Only the suffix that labels from a macro get can have a '.'.
+                let name = token.1.trim_end_matches(':');
+                let base = match name.rfind('.') {
+                    Some(dot) if line.expanded => &name[.. dot],
+                    _ => name,
+                };
+
+                if !is_valid_name(base) || labels.contains_key(name) || self.constants.contains_key(name) {
+                    self.errors.push(error(line.line, token, NAME_FORM));
+                } else {
+                    labels.insert(String::from(name), index);
+                }
+            }
+
+            if count < line.tokens.len() {
+                index += 1;
+            }
+        }
+
+        let mut instructions = vec![];
+        let mut source_map = vec![];
+        for line in self.lines.iter().filter(|line| line.label_count() < line.tokens.len()) {
+            let index = instructions.len() as i64;
+
+            // Rewrite names into numbers, remembering where each token starts in the new text so
+            // that errors can point at the original column.
+            let mut text = String::new();
+            let mut starts = vec![];
+            for (i, (_, token)) in line.tokens[line.label_count() ..].iter().enumerate() {
+                if i > 0 {
+                    text.push(' ');
+                }
+
+                starts.push(text.chars().count() + 1);
+                let resolved = if i == 0 {
+                    token.clone()
+                } else if let Some(target) = labels.get(token) {
+                    (*target as i64 - index).to_string()
+                } else if let Some(value) = self.constants.get(token) {
+                    value.to_string()
+                } else {
+                    token.clone()
+                };
+
+                text.push_str(&resolved);
+            }
+
+            match text.parse::<I>() {
+                Ok(instruction) => {
+                    instructions.push(instruction);
+                    source_map.push(line.origin);
+                },
+                Err(mut e) => {
+                    let original = &line.tokens[line.label_count() ..];
+                    e.column = match starts.iter().rposition(|start| *start <= e.column) {
+                        Some(k) if e.column == starts[k] => original[k].0,
+                        Some(k) if k + 1 < starts.len() || !e.token.is_empty() => original[k].0 + (e.column - starts[k]),
+                        _ => original.last().map(|t| t.0 + t.1.chars().count()).unwrap_or(1),
+                    };
+
+                    self.errors.push(e.at_line(line.line));
+                },
+            }
+        }
+
+        if self.errors.is_empty() {
+            labels.retain(|name, _| !name.contains('.'));
+            Ok(Assembly {
+                program : Program {
+                    instructions,
+                },
+                source_map,
+                labels,
+            })
+        } else {
+            self.errors.sort_by_key(|e| (e.line, e.column));
+            Err(self.errors)
+        }
+    }
+}
+
+// Assembles source into a program. On top of one instruction per line, the source can have:
+//
+// # comments, and blank lines
+// top: add a 1    labels, which can be used instead of relative jump offsets: jgz a top
+// .const N 10     constants, which can be used wherever a value can
+// .macro twice r  macros with parameters, ending with .endm. Labels inside a macro are local to
+//     add r r     each use of it.
+// .endm
+pub fn assemble<I>(source : &str) -> Result<Assembly<I>, Vec<ParseError>>
+where I : InstructionSet {
+    let mut assembler = Assembler::default();
+    assembler.read(source);
+    assembler.assemble()
+}
+
+impl<I> Assembly<I> {
+    // The source line an instruction came from.
+    pub fn source_line(&self, ip : usize) -> Option<usize> {
+        self.source_map.get(ip).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels() {
+        let assembly : Assembly = assemble(
+r"# counts down from 5
+    set a 5
+top:
+    sub a 1
+    snd a      # report progress
+    jgz a top
+    jgz 1 end
+
+    set b 1
+end:").unwrap();
+
+        assert_eq!(format!("{}", assembly.program), "set a 5\nsub a 1\nsnd a\njgz a -2\njgz 1 2\nset b 1\n");
+        assert_eq!(assembly.source_map, vec![2, 4, 5, 6, 7, 9]);
+        assert_eq!(assembly.source_line(3), Some(6));
+        assert_eq!(assembly.source_line(6), None);
+        assert_eq!(assembly.labels.get("end"), Some(&6));
+    }
+
+    #[test]
+    fn constants_and_macros() {
+        let assembly : Assembly<Assembunny> = assemble(
+r".const COUNT 3
+.const START COUNT
+.macro add_into dest src
+again: inc dest
+    dec src
+    jnz src again
+.endm
+
+    cpy START b
+    cpy 10 c
+start: add_into a b
+    add_into a c
+    jnz 0 start").unwrap();
+
+        assert_eq!(format!("{}", assembly.program),
+r"cpy 3 b
+cpy 10 c
+inc a
+dec b
+jnz b -2
+inc a
+dec c
+jnz c -2
+jnz 0 -6
+");
+        assert_eq!(assembly.source_map, vec![9, 10, 11, 11, 11, 12, 12, 12, 13]);
+        assert_eq!(assembly.labels.keys().collect::<Vec<&String>>(), vec!["start"]);
+
+        let mut machine = Machine::new(assembly.program);
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 13);
+    }
+
+    #[test]
+    fn errors() {
+        let errors = assemble::<Instruction>(
+r"loop: set a 1
+    jgz a nowhere
+loop: snd a
+a: snd a
+.const X
+.macro m
+.bogus").err().unwrap();
+
+        assert_eq!(errors, vec![
+            ParseError::new(11, "nowhere", &["<reg|value>"]).at_line(2),
+            ParseError::new(1, "loop:", &[NAME_FORM]).at_line(3),
+            ParseError::new(1, "a:", &[NAME_FORM]).at_line(4),
+            ParseError::new(9, "", &[".const <name> <value>"]).at_line(5),
+            ParseError::new(1, "", &[".endm"]).at_line(6),
+        ]);
+
+        // Source labels can't look like the ones a macro makes, inside a macro or out.
+        let errors = assemble::<Instruction>(
+r"foo.bar: snd a
+.macro once
+in.side: snd a
+.endm
+once
+foo.1: snd a").err().unwrap();
+        assert_eq!(errors, vec![
+            ParseError::new(1, "foo.bar:", &[NAME_FORM]).at_line(1),
+            ParseError::new(1, "in.side.1:", &[NAME_FORM]).at_line(3),
+            ParseError::new(1, "foo.1:", &[NAME_FORM]).at_line(6),
+        ]);
+    }
+
+    #[test]
+    fn macro_arguments() {
+        let errors = assemble::<Instruction>(".macro two x y\nset x y\n.endm\ntwo a\ntwo a 1 2").err().unwrap();
+        assert_eq!(errors, vec![
+            ParseError::new(6, "", &["2 arguments to two"]).at_line(4),
+            ParseError::new(9, "2", &["2 arguments to two"]).at_line(5),
+        ]);
+
+        let errors = assemble::<Instruction>(".macro loop\nloop\n.endm\nloop").err().unwrap();
+        assert_eq!(errors, vec![ParseError::new(1, "loop", &["a macro that doesn't use itself"]).at_line(2)]);
+    }
+}
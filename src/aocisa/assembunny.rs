@@ -0,0 +1,145 @@
+use super::*;
+
+instruction_set! {
+    // The assembunny instruction set from 2016 days 12, 23 and 25. Every operand accepts a
+    // register or a value, because tgl can turn a valid instruction into one that writes to a
+    // value. Those instructions are skipped.
+    pub enum Assembunny {
+        Cpy = "cpy" (src : RegisterOrValue, dest : RegisterOrValue) => |r| {
+            match *dest {
+                RegisterOrValue::Reg(reg) => Effect::Write(reg, r.evaluate(src)),
+                RegisterOrValue::Val(_) => Effect::Nop,
+            }
+        },
+        Inc = "inc" (rv : RegisterOrValue) => |r| {
+            match *rv {
+                RegisterOrValue::Reg(reg) => Effect::write(reg, r.add(*r.get_reg(reg), 1)),
+                RegisterOrValue::Val(_) => Effect::Nop,
+            }
+        },
+        Dec = "dec" (rv : RegisterOrValue) => |r| {
+            match *rv {
+                RegisterOrValue::Reg(reg) => Effect::write(reg, r.sub(*r.get_reg(reg), 1)),
+                RegisterOrValue::Val(_) => Effect::Nop,
+            }
+        },
+        Jnz = "jnz" (cond : RegisterOrValue, offset : RegisterOrValue) => |r| {
+            Effect::Jump(if r.evaluate(cond) != 0 { Some(r.evaluate(offset)) } else { None })
+        },
+        Tgl = "tgl" (offset : RegisterOrValue) => |r| Effect::Toggle(r.evaluate(offset)),
+        Out = "out" (rv : RegisterOrValue) => |r| Effect::Send(r.evaluate(rv)),
+    }
+    toggle = toggle_assembunny;
+}
+
+fn toggle_assembunny(instruction : &Assembunny) -> Assembunny {
+    match instruction.clone() {
+        Assembunny::Inc(a) => Assembunny::Dec(a),
+        Assembunny::Dec(a) |
+        Assembunny::Tgl(a) |
+        Assembunny::Out(a) => Assembunny::Inc(a),
+        Assembunny::Jnz(a, b) => Assembunny::Cpy(a, b),
+        Assembunny::Cpy(a, b) => Assembunny::Jnz(a, b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn load(input : &str) -> Program<Assembunny> {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn load_print() {
+        let input =
+r"cpy 41 a
+cpy a b
+inc a
+dec b
+jnz a 2
+jnz 1 b
+tgl c
+out -3";
+        assert_eq!(input, format!("{}", load(input)).trim());
+    }
+
+    #[test]
+    fn simple() {
+        let mut machine = Machine::new(load(
+r"cpy 41 a
+inc a
+inc a
+dec a
+jnz a 2
+dec a"));
+
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 42);
+    }
+
+    #[test]
+    fn toggle() {
+        let mut machine = Machine::new(load(
+r"cpy 2 a
+tgl a
+tgl a
+tgl a
+cpy 1 a
+dec a
+dec a"));
+
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 3);
+        assert_eq!(format!("{}", machine.program()),
+r"cpy 2 a
+tgl a
+tgl a
+inc a
+jnz 1 a
+dec a
+dec a
+");
+    }
+
+    #[test]
+    fn toggle_outside_program() {
+        let mut program = load("tgl 5\ninc 3");
+        assert!(!program.toggle(5));
+        assert!(program.toggle(1));
+        assert_eq!(program.instructions[1], Assembunny::Dec(RegisterOrValue::Val(3)));
+
+        let mut machine = Machine::new(load("tgl 5\ncpy 1 2\ninc a"));
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 1);
+    }
+
+    #[test]
+    fn clock_signal() {
+        let program = load(
+r"cpy a b
+out b
+inc b
+out b
+dec b
+jnz 1 -4");
+
+        let mut machine = Machine::new(program.clone());
+        let cycle = machine.find_output_cycle(1000).unwrap();
+        assert_eq!(cycle, OutputCycle { prefix : vec![0], cycle : vec![1, 0] });
+        assert!(cycle.is_clock_signal());
+
+        let mut machine = Machine::new(program);
+        *machine.registers_mut().get_reg_mut('a') = 1;
+        let cycle = machine.find_output_cycle(1000).unwrap();
+        assert_eq!(cycle.cycle, vec![2, 1]);
+        assert!(!cycle.is_clock_signal());
+    }
+
+    #[test]
+    fn output_stops() {
+        let mut machine = Machine::new(load("out 0\nout 1"));
+        assert_eq!(machine.find_output_cycle(1000), None);
+    }
+}
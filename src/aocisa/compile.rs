@@ -0,0 +1,464 @@
+use super::*;
+use std::collections::VecDeque;
+
+// An operand with the register already turned into an index into the register array.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Arg {
+    Reg(u8),
+    Val(i64),
+}
+
+// One instruction of a compiled program. Jumps with constant offsets already know the index they
+// go to, which is usize::MAX for anything before the start of the program, like offset_ip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Code {
+    Set(u8, Arg),
+    Add(u8, Arg),
+    Sub(u8, Arg),
+    Mul(u8, Arg),
+    Mod(u8, Arg),
+    Goto(usize),
+    JumpNonZero(Arg, usize),
+    JumpPositive(Arg, usize),
+    JumpNonZeroBy(Arg, Arg),
+    JumpPositiveBy(Arg, Arg),
+    Send(Arg),
+    Receive(u8),
+    Toggle(Arg),
+    Nop,
+}
+
+// A machine that runs a program compiled to Codes instead of interpreting the instructions, with
+// the registers in a plain array. It only supports 64 bit registers that fault on overflow, like
+// Overflow::Checked, and has no tracing or history, but it runs many times faster than Machine.
+#[derive(Clone)]
+pub struct CompiledMachine<I = Instruction> {
+    program : Program<I>,
+    code : Vec<Code>,
+    registers : [i64 ; FIXED_REGISTERS],
+    ip : usize,
+    inbox : VecDeque<i64>,
+    outbox : VecDeque<i64>,
+    send_count : usize,
+    steps : u64,
+}
+
+fn compile_reg(reg : char) -> u8 {
+    reg.slot().unwrap_or_else(|| panic!("register {} can't be compiled", reg)) as u8
+}
+
+fn compile_arg(rv : &RegisterOrValue) -> Arg {
+    match *rv {
+        RegisterOrValue::Reg(reg) => Arg::Reg(compile_reg(reg)),
+        RegisterOrValue::Val(value) => Arg::Val(value),
+    }
+}
+
+impl Code {
+    // Compiles the instruction at the index.
+    pub fn compile(op : &Op, index : usize) -> Code {
+        match *op {
+            Op::Set(reg, ref rv) => Code::Set(compile_reg(reg), compile_arg(rv)),
+            Op::Add(reg, ref rv) => Code::Add(compile_reg(reg), compile_arg(rv)),
+            Op::Sub(reg, ref rv) => Code::Sub(compile_reg(reg), compile_arg(rv)),
+            Op::Mul(reg, ref rv) => Code::Mul(compile_reg(reg), compile_arg(rv)),
+            Op::Mod(reg, ref rv) => Code::Mod(compile_reg(reg), compile_arg(rv)),
+            Op::Jump(ref cond, ref offset) => {
+                match (cond.constant(), offset) {
+                    (Some(false), _) => Code::Nop,
+                    (Some(true), &RegisterOrValue::Val(offset)) => Code::Goto(offset_ip(index, offset)),
+                    (_, &RegisterOrValue::Val(offset)) => {
+                        let target = offset_ip(index, offset);
+                        match *cond {
+                            Condition::NonZero(ref rv) => Code::JumpNonZero(compile_arg(rv), target),
+                            Condition::Positive(ref rv) => Code::JumpPositive(compile_arg(rv), target),
+                        }
+                    },
+                    (_, offset) => {
+                        match *cond {
+                            Condition::NonZero(ref rv) => Code::JumpNonZeroBy(compile_arg(rv), compile_arg(offset)),
+                            Condition::Positive(ref rv) => Code::JumpPositiveBy(compile_arg(rv), compile_arg(offset)),
+                        }
+                    },
+                }
+            },
+            Op::Send(ref rv) => Code::Send(compile_arg(rv)),
+            Op::Receive(reg) => Code::Receive(compile_reg(reg)),
+            Op::Toggle(ref rv) => Code::Toggle(compile_arg(rv)),
+            Op::Nop => Code::Nop,
+        }
+    }
+}
+
+impl<I> Program<I>
+where I : Analyze {
+    pub fn compile(&self) -> Vec<Code> {
+        self.instructions.iter().enumerate().map(|(i, instruction)| Code::compile(&instruction.op(), i)).collect()
+    }
+}
+
+impl<I> CompiledMachine<I>
+where I : InstructionSet<Register = char> + Analyze {
+    pub fn new(program : Program<I>) -> CompiledMachine<I> {
+        CompiledMachine {
+            code : program.compile(),
+            program,
+            registers : [0 ; FIXED_REGISTERS],
+            ip : 0,
+            inbox : VecDeque::new(),
+            outbox : VecDeque::new(),
+            send_count : 0,
+            steps : 0,
+        }
+    }
+
+    // The program as it is now, including anything tgl did to it.
+    pub fn program(&self) -> &Program<I> {
+        &self.program
+    }
+
+    pub fn code(&self) -> &[Code] {
+        &self.code
+    }
+
+    // Only registers with a slot can be compiled, so None for any other register.
+    pub fn get_reg(&self, reg : char) -> Option<i64> {
+        reg.slot().map(|slot| self.registers[slot])
+    }
+
+    // Returns false if the register doesn't have a slot.
+    pub fn set_reg(&mut self, reg : char, value : i64) -> bool {
+        match reg.slot() {
+            Some(slot) => {
+                self.registers[slot] = value;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // A copy of the registers in the form Machine uses.
+    pub fn registers(&self) -> RegisterHolder {
+        let mut registers = RegisterHolder::new();
+        for (slot, value) in self.registers.iter().enumerate() {
+            *registers.get_reg_mut(char::from_slot(slot)) = *value;
+        }
+        registers
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn send_count(&self) -> usize {
+        self.send_count
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn push_input(&mut self, value : i64) {
+        self.inbox.push_back(value);
+    }
+
+    pub fn pop_output(&mut self) -> Option<i64> {
+        self.outbox.pop_front()
+    }
+
+    pub fn status(&self) -> MachineStatus {
+        match self.code.get(self.ip) {
+            None => MachineStatus::Halted,
+            Some(&Code::Receive(_)) if self.inbox.is_empty() => MachineStatus::Blocked,
+            Some(&code) => {
+                match self.fault(code) {
+                    Some(fault) => MachineStatus::Faulted(fault),
+                    None => MachineStatus::Ready,
+                }
+            },
+        }
+    }
+
+    // Why the code can't run with the current registers, if it can't. execute stops at the same
+    // codes without going through here.
+    fn fault(&self, code : Code) -> Option<Fault> {
+        let value = |arg| {
+            match arg {
+                Arg::Reg(reg) => self.registers[reg as usize],
+                Arg::Val(value) => value,
+            }
+        };
+
+        let overflowed = match code {
+            Code::Add(reg, a) => self.registers[reg as usize].checked_add(value(a)).is_none(),
+            Code::Sub(reg, a) => self.registers[reg as usize].checked_sub(value(a)).is_none(),
+            Code::Mul(reg, a) => self.registers[reg as usize].checked_mul(value(a)).is_none(),
+            Code::Mod(_, a) if value(a) == 0 => return Some(Fault::DivideByZero),
+            _ => false,
+        };
+
+        if overflowed {
+            Some(Fault::Overflow)
+        } else {
+            None
+        }
+    }
+
+    // Executes at most one instruction. Doesn't advance if the machine is blocked or halted.
+    pub fn step(&mut self) -> MachineStatus {
+        self.execute(1);
+        self.status()
+    }
+
+    // Steps until the machine blocks on a receive, faults or halts.
+    pub fn run(&mut self) -> MachineStatus {
+        self.execute(u64::MAX);
+        self.status()
+    }
+
+    // Runs at most max_steps instructions, keeping the ip and registers in locals so that the
+    // loop doesn't go through self for every step. Stops without advancing at a code that faults.
+    fn execute(&mut self, max_steps : u64) {
+        let mut ip = self.ip;
+        let mut steps = 0;
+        let mut regs = self.registers;
+
+        macro_rules! arg {
+            ($arg:expr) => {
+                match $arg {
+                    Arg::Reg(reg) => regs[reg as usize],
+                    Arg::Val(value) => value,
+                }
+            };
+        }
+
+        macro_rules! arithmetic {
+            ($reg:expr, $arg:expr, $op:ident) => {{
+                let value = arg!($arg);
+                let reg = $reg as usize;
+                match regs[reg].$op(value) {
+                    Some(result) => regs[reg] = result,
+                    None => break,
+                }
+            }};
+        }
+
+        while steps < max_steps {
+            let code = match self.code.get(ip) {
+                Some(code) => *code,
+                None => break,
+            };
+
+            let mut next_ip = ip + 1;
+            match code {
+                Code::Set(reg, a) => regs[reg as usize] = arg!(a),
+                Code::Add(reg, a) => arithmetic!(reg, a, checked_add),
+                Code::Sub(reg, a) => arithmetic!(reg, a, checked_sub),
+                Code::Mul(reg, a) => arithmetic!(reg, a, checked_mul),
+                Code::Mod(reg, a) => {
+                    // Like RegisterHolder::rem, i64::MIN % -1 is just 0.
+                    match arg!(a) {
+                        0 => break,
+                        b => regs[reg as usize] = regs[reg as usize].wrapping_rem(b),
+                    }
+                },
+                Code::Goto(target) => next_ip = target,
+                Code::JumpNonZero(cond, target) => if arg!(cond) != 0 { next_ip = target },
+                Code::JumpPositive(cond, target) => if arg!(cond) > 0 { next_ip = target },
+                Code::JumpNonZeroBy(cond, offset) => if arg!(cond) != 0 { next_ip = offset_ip(ip, arg!(offset)) },
+                Code::JumpPositiveBy(cond, offset) => if arg!(cond) > 0 { next_ip = offset_ip(ip, arg!(offset)) },
+                Code::Send(a) => {
+                    self.outbox.push_back(arg!(a));
+                    self.send_count += 1;
+                },
+                Code::Receive(reg) => {
+                    match self.inbox.pop_front() {
+                        Some(value) => regs[reg as usize] = value,
+                        None => break,
+                    }
+                },
+                Code::Toggle(a) => {
+                    // Only the toggled instruction needs compiling again, since jump targets are
+                    // absolute.
+                    let target = offset_ip(ip, arg!(a));
+                    if self.program.toggle(target) {
+                        self.code[target] = Code::compile(&self.program.instructions[target].op(), target);
+                    }
+                },
+                Code::Nop => {},
+            }
+
+            ip = next_ip;
+            steps += 1;
+        }
+
+        self.ip = ip;
+        self.steps += steps;
+        self.registers = regs;
+    }
+}
+
+impl<I> Process for CompiledMachine<I>
+where I : InstructionSet<Register = char> + Analyze {
+    fn status(&self) -> MachineStatus {
+        CompiledMachine::status(self)
+    }
+
+    fn run(&mut self) -> MachineStatus {
+        CompiledMachine::run(self)
+    }
+
+    fn push_input(&mut self, value : i64) {
+        CompiledMachine::push_input(self, value)
+    }
+
+    fn pop_output(&mut self) -> Option<i64> {
+        CompiledMachine::pop_output(self)
+    }
+
+    fn steps(&self) -> u64 {
+        CompiledMachine::steps(self)
+    }
+
+    fn send_count(&self) -> usize {
+        CompiledMachine::send_count(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Checks that the compiled machine ends up exactly where the interpreter does.
+    fn compare<I>(program : Program<I>, setup : &[(char, i64)])
+    where I : InstructionSet<Register = char> + Analyze + Clone + PartialEq + fmt::Debug {
+        let mut machine = Machine::new(program.clone());
+        let mut compiled = CompiledMachine::new(program);
+        for &(reg, value) in setup {
+            machine.registers_mut().set_reg(reg, value).unwrap();
+            assert!(compiled.set_reg(reg, value));
+        }
+
+        assert_eq!(compiled.run(), machine.run());
+        assert_eq!(compiled.registers(), *machine.registers());
+        assert_eq!(compiled.ip(), machine.ip());
+        assert_eq!(compiled.steps(), machine.steps());
+        assert_eq!(compiled.program(), machine.program());
+    }
+
+    #[test]
+    fn codes() {
+        let program = Program::load("set a 3\njgz a -1\njnz 1 -5\njgz 0 2\njnz a b\nrcv z");
+        assert_eq!(program.compile(), vec![
+            Code::Set(0, Arg::Val(3)),
+            Code::JumpPositive(Arg::Reg(0), 0),
+            Code::Goto(usize::MAX),
+            Code::Nop,
+            Code::JumpNonZeroBy(Arg::Reg(0), Arg::Reg(1)),
+            Code::Receive(25),
+        ]);
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        compare(Program::load(
+r"set a 1
+add a 2
+mul a a
+mod a 5
+set b 10
+sub b 1
+mul a 2
+jgz b -2
+snd a
+jnz 1 -10"), &[]);
+
+        compare(
+r"cpy 2 a
+tgl a
+tgl a
+tgl a
+cpy 1 a
+dec a
+dec a".parse::<Program<Assembunny>>().unwrap(), &[]);
+
+        compare(
+r"cpy a b
+dec b
+cpy a d
+cpy 0 a
+cpy b c
+inc a
+dec c
+jnz c -2
+dec d
+jnz d -5
+dec b
+cpy b c
+cpy c d
+dec d
+inc c
+jnz d -2
+tgl c
+cpy -16 c
+jnz 1 c
+cpy 73 c
+jnz 71 d
+inc a
+inc d
+jnz d -2
+inc c
+jnz c -5".parse::<Program<Assembunny>>().unwrap(), &[('a', 7)]);
+    }
+
+    #[test]
+    fn step_and_block() {
+        let mut compiled = CompiledMachine::new(Program::load("snd 5\nrcv a\nadd a 1"));
+        assert_eq!(compiled.step(), MachineStatus::Blocked);
+        assert_eq!(compiled.pop_output(), Some(5));
+        assert_eq!(compiled.run(), MachineStatus::Blocked);
+        assert_eq!(compiled.steps(), 1);
+
+        compiled.push_input(41);
+        assert_eq!(compiled.run(), MachineStatus::Halted);
+        assert_eq!(compiled.get_reg('a'), Some(42));
+        assert_eq!(compiled.get_reg('?'), None);
+        assert!(!compiled.set_reg('?', 1));
+    }
+
+    #[test]
+    fn faults() {
+        compare(Program::load("set a 5\nmod a b"), &[]);
+        compare(Program::load("set a -9223372036854775808\nmod a -1"), &[]);
+
+        let mut compiled = CompiledMachine::new(Program::load("set a 5\nmod a b\nadd a 9223372036854775807"));
+        assert_eq!(compiled.run(), MachineStatus::Faulted(Fault::DivideByZero));
+        assert_eq!((compiled.ip(), compiled.steps()), (1, 1));
+
+        // Fixing the cause lets it continue, until the next fault.
+        assert!(compiled.set_reg('b', 3));
+        assert_eq!(compiled.run(), MachineStatus::Faulted(Fault::Overflow));
+        assert_eq!((compiled.ip(), compiled.get_reg('a')), (2, Some(2)));
+        assert_eq!(compiled.step(), MachineStatus::Faulted(Fault::Overflow));
+        assert_eq!(compiled.steps(), 2);
+    }
+
+    #[test]
+    fn duet() {
+        let program = Program::load(
+r"snd 1
+snd 2
+snd p
+rcv a
+rcv b
+rcv c
+rcv d");
+
+        let mut machines = vec![CompiledMachine::new(program.clone()), CompiledMachine::new(program)];
+        assert!(machines[1].set_reg('p', 1));
+        let mut scheduler = Scheduler::new(machines);
+        assert_eq!(scheduler.run(), SchedulerOutcome::Deadlocked);
+        assert_eq!(scheduler.send_counts(), vec![3, 3]);
+        assert_eq!(scheduler.machines()[0].get_reg('c'), Some(1));
+    }
+}
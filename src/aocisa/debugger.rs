@@ -0,0 +1,442 @@
+use super::*;
+use std::fmt::Write as FmtWrite;
+
+// How many steps the debugger remembers for "back".
+const HISTORY_CAPACITY : usize = 100000;
+
+const HELP : &str = r"commands, using instruction numbers as shown by list:
+  step [n]                 run one instruction, or n of them
+  continue                 run until a breakpoint, watchpoint, halt or blocked receive
+  back [n]                 undo the last step, or the last n
+  break <n>                stop before running instruction n
+  break <reg> <op> <value> stop when the condition becomes true. op is == != < <= > >=
+  watch <reg>              stop whenever the register changes
+  delete <id>              remove a breakpoint or watchpoint
+  info                     list breakpoints and watchpoints
+  print [reg]              show one register, or every register that isn't 0
+  set <reg> <value>        change a register
+  push <value>             add a value to the inbox
+  queues                   show the inbox and outbox
+  list                     disassemble the program
+  quit";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+    // Stop before running the instruction at this index.
+    Line(usize),
+
+    // Stop when the condition goes from false to true.
+    Condition(char, Comparison, i64),
+
+    // Stop whenever the register's value changes.
+    Watch(char),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    Step(usize),
+    Continue,
+    Back(usize),
+    Break(Breakpoint),
+    Delete(usize),
+    Info,
+    Print(Option<char>),
+    Set(char, i64),
+    Push(i64),
+    Queues,
+    List,
+    Help,
+    Quit,
+}
+
+// A debugger for one machine, driven by text commands so that it can sit behind a REPL.
+pub struct Debugger<I = Instruction>
+where I : InstructionSet<Register = char> {
+    machine : Machine<I>,
+    breakpoints : Vec<(usize, Breakpoint)>,
+    next_id : usize,
+}
+
+impl Comparison {
+    fn parse(input : &str) -> Option<Comparison> {
+        match input {
+            "==" => Some(Comparison::Eq),
+            "!=" => Some(Comparison::Ne),
+            "<" => Some(Comparison::Lt),
+            "<=" => Some(Comparison::Le),
+            ">" => Some(Comparison::Gt),
+            ">=" => Some(Comparison::Ge),
+            _ => None,
+        }
+    }
+
+    pub fn holds(&self, a : i64, b : i64) -> bool {
+        match *self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        })
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Line(ip) => write!(f, "break at {}", ip),
+            Breakpoint::Condition(reg, cmp, value) => write!(f, "break if {} {} {}", reg, cmp, value),
+            Breakpoint::Watch(reg) => write!(f, "watch {}", reg),
+        }
+    }
+}
+
+fn parse_register(input : Option<&str>) -> Result<char, String> {
+    match input {
+        Some(text) => char::parse_operand(text).map_err(|_| format!("invalid register: {}", text)),
+        None => Err(String::from("missing register")),
+    }
+}
+
+fn parse_number<T>(input : Option<&str>, what : &str) -> Result<T, String>
+where T : FromStr {
+    match input {
+        Some(text) => text.parse().map_err(|_| format!("invalid {}: {}", what, text)),
+        None => Err(format!("missing {}", what)),
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(input : &str) -> Result<Command, String> {
+        let mut words = input.split_whitespace();
+        let command = match words.next() {
+            Some(word) => word,
+            None => return Err(String::from("no command")),
+        };
+
+        let optional_count = |word : Option<&str>| -> Result<usize, String> {
+            match word {
+                Some(_) => parse_number(word, "count"),
+                None => Ok(1),
+            }
+        };
+
+        let parsed = match command {
+            "s" | "step" => Command::Step(optional_count(words.next())?),
+            "c" | "continue" => Command::Continue,
+            "back" => Command::Back(optional_count(words.next())?),
+            "b" | "break" => {
+                let first = words.next();
+                match first.map(|w| w.parse::<usize>()) {
+                    Some(Ok(ip)) => Command::Break(Breakpoint::Line(ip)),
+                    _ => {
+                        let reg = parse_register(first)?;
+                        let cmp = words.next().and_then(Comparison::parse).ok_or_else(|| String::from("expected one of == != < <= > >="))?;
+                        let value = parse_number(words.next(), "value")?;
+                        Command::Break(Breakpoint::Condition(reg, cmp, value))
+                    },
+                }
+            },
+            "w" | "watch" => Command::Break(Breakpoint::Watch(parse_register(words.next())?)),
+            "d" | "delete" => Command::Delete(parse_number(words.next(), "breakpoint id")?),
+            "i" | "info" => Command::Info,
+            "p" | "print" => {
+                match words.next() {
+                    Some(word) => Command::Print(Some(parse_register(Some(word))?)),
+                    None => Command::Print(None),
+                }
+            },
+            "set" => {
+                let reg = parse_register(words.next())?;
+                Command::Set(reg, parse_number(words.next(), "value")?)
+            },
+            "push" => Command::Push(parse_number(words.next(), "value")?),
+            "queues" => Command::Queues,
+            "l" | "list" => Command::List,
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("unknown command {}. try help", command)),
+        };
+
+        match words.next() {
+            Some(extra) => Err(format!("unexpected {}", extra)),
+            None => Ok(parsed),
+        }
+    }
+}
+
+impl<I> Debugger<I>
+where I : InstructionSet<Register = char> + Analyze {
+    pub fn new(program : Program<I>) -> Debugger<I> {
+        let mut machine = Machine::new(program);
+        machine.set_history_capacity(HISTORY_CAPACITY);
+        Debugger {
+            machine,
+            breakpoints : vec![],
+            next_id : 1,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<I> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<I> {
+        &mut self.machine
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    // Where the machine is, like "4: inc a".
+    pub fn location(&self) -> String {
+        match self.machine.program().instructions.get(self.machine.ip()) {
+            Some(instruction) => format!("{}: {}", self.machine.ip(), instruction),
+            None => format!("halted at {} after {} steps", self.machine.ip(), self.machine.steps()),
+        }
+    }
+
+    // Runs one step and returns why it should stop there, if it should.
+    fn step_checked(&mut self) -> Result<Option<String>, String> {
+        let before = self.machine.registers().clone();
+        match self.machine.status() {
+            MachineStatus::Halted => return Err(String::from("halted")),
+            MachineStatus::Blocked => return Err(String::from("blocked on receive with an empty inbox")),
+            MachineStatus::Faulted(fault) => return Err(format!("faulted: {}", fault)),
+            MachineStatus::Ready => {},
+        }
+
+        self.machine.step();
+
+        let registers = self.machine.registers();
+        for &(id, breakpoint) in &self.breakpoints {
+            match breakpoint {
+                Breakpoint::Line(ip) if ip == self.machine.ip() => {
+                    return Ok(Some(format!("breakpoint {}", id)));
+                },
+                Breakpoint::Condition(reg, cmp, value)
+                    if cmp.holds(*registers.get_reg(reg), value) && !cmp.holds(*before.get_reg(reg), value) => {
+                    return Ok(Some(format!("breakpoint {}: {} {} {}", id, reg, cmp, value)));
+                },
+                Breakpoint::Watch(reg) if registers.get_reg(reg) != before.get_reg(reg) => {
+                    return Ok(Some(format!("watchpoint {}: {} {} -> {}", id, reg, before.get_reg(reg), registers.get_reg(reg))));
+                },
+                _ => {},
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Runs at most max_steps steps, stopping early for breakpoints or if the machine can't go on.
+    fn run_until_stop(&mut self, max_steps : Option<usize>) -> String {
+        let mut taken = 0;
+        loop {
+            if max_steps.is_some_and(|max| taken >= max) {
+                return self.location();
+            }
+
+            match self.step_checked() {
+                Ok(Some(reason)) => return format!("{}\n{}", reason, self.location()),
+                Ok(None) => taken += 1,
+                Err(reason) => {
+                    return if taken == 0 {
+                        reason
+                    } else {
+                        format!("{}\n{}", reason, self.location())
+                    };
+                },
+            }
+        }
+    }
+
+    // Runs a command and returns what to show for it.
+    pub fn execute(&mut self, command : &Command) -> String {
+        match *command {
+            Command::Step(count) => self.run_until_stop(Some(count)),
+            Command::Continue => self.run_until_stop(None),
+            Command::Back(count) => {
+                for i in 0 .. count {
+                    if !self.machine.step_back() {
+                        return format!("no more history after going back {}\n{}", i, self.location());
+                    }
+                }
+
+                self.location()
+            },
+            Command::Break(breakpoint) => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.breakpoints.push((id, breakpoint));
+                format!("{}: {}", id, breakpoint)
+            },
+            Command::Delete(id) => {
+                match self.breakpoints.iter().position(|&(i, _)| i == id) {
+                    Some(index) => format!("deleted {}: {}", id, self.breakpoints.remove(index).1),
+                    None => format!("no breakpoint {}", id),
+                }
+            },
+            Command::Info => {
+                let mut out = String::new();
+                for &(id, breakpoint) in &self.breakpoints {
+                    writeln!(out, "{}: {}", id, breakpoint).unwrap();
+                }
+
+                if out.is_empty() {
+                    String::from("no breakpoints")
+                } else {
+                    out.trim_end().to_string()
+                }
+            },
+            Command::Print(Some(reg)) => format!("{} = {}", reg, self.machine.registers().get_reg(reg)),
+            Command::Print(None) => {
+                let values = self.machine.registers().values();
+                if values.is_empty() {
+                    String::from("all registers are 0")
+                } else {
+                    values.iter().map(|&(r, v)| format!("{} = {}", r, v)).collect::<Vec<String>>().join("\n")
+                }
+            },
+            Command::Set(reg, value) => {
+                match self.machine.registers_mut().set_reg(reg, value) {
+                    Ok(()) => format!("{} = {}", reg, self.machine.registers().get_reg(reg)),
+                    Err(fault) => format!("can't set {} to {}: {}", reg, value, fault),
+                }
+            },
+            Command::Push(value) => {
+                self.machine.push_input(value);
+                format!("inbox has {} values", self.machine.inbox().len())
+            },
+            Command::Queues => {
+                let show = |queue : &std::collections::VecDeque<i64>| {
+                    queue.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(" ")
+                };
+
+                format!("inbox: [{}]\noutbox: [{}]", show(self.machine.inbox()), show(self.machine.outbox()))
+            },
+            Command::List => format!("{}", self.machine.program().listing()).trim_end().to_string(),
+            Command::Help => String::from(HELP),
+            Command::Quit => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(debugger : &mut Debugger<Assembunny>, command : &str) -> String {
+        debugger.execute(&command.parse().unwrap())
+    }
+
+    fn multiply() -> Debugger<Assembunny> {
+        Debugger::new(r"cpy 3 b
+cpy 4 d
+cpy b c
+inc a
+dec c
+jnz c -2
+dec d
+jnz d -5
+out a".parse().unwrap())
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!("s".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 10".parse(), Ok(Command::Step(10)));
+        assert_eq!("break 4".parse(), Ok(Command::Break(Breakpoint::Line(4))));
+        assert_eq!("b a >= 10".parse(), Ok(Command::Break(Breakpoint::Condition('a', Comparison::Ge, 10))));
+        assert_eq!("watch c".parse(), Ok(Command::Break(Breakpoint::Watch('c'))));
+        assert_eq!("set b -3".parse(), Ok(Command::Set('b', -3)));
+        assert_eq!("print".parse(), Ok(Command::Print(None)));
+        assert_eq!("print 3".parse::<Command>(), Err(String::from("invalid register: 3")));
+        assert_eq!("b a ~ 1".parse::<Command>(), Err(String::from("expected one of == != < <= > >=")));
+        assert_eq!("step x".parse::<Command>(), Err(String::from("invalid count: x")));
+        assert_eq!("step 1 2".parse::<Command>(), Err(String::from("unexpected 2")));
+        assert_eq!("frob".parse::<Command>(), Err(String::from("unknown command frob. try help")));
+    }
+
+    #[test]
+    fn step_and_print() {
+        let mut debugger = multiply();
+        assert_eq!(run(&mut debugger, "step"), "1: cpy 4 d");
+        assert_eq!(run(&mut debugger, "step 3"), "4: dec c");
+        assert_eq!(run(&mut debugger, "print"), "a = 1\nb = 3\nc = 3\nd = 4");
+        assert_eq!(run(&mut debugger, "back 2"), "2: cpy b c");
+        assert_eq!(run(&mut debugger, "print a"), "a = 0");
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = multiply();
+        assert_eq!(run(&mut debugger, "break 6"), "1: break at 6");
+        assert_eq!(run(&mut debugger, "continue"), "breakpoint 1\n6: dec d");
+        assert_eq!(run(&mut debugger, "print a"), "a = 3");
+        assert_eq!(run(&mut debugger, "delete 1"), "deleted 1: break at 6");
+
+        assert_eq!(run(&mut debugger, "break a >= 7"), "2: break if a >= 7");
+        assert_eq!(run(&mut debugger, "continue"), "breakpoint 2: a >= 7\n4: dec c");
+        assert_eq!(run(&mut debugger, "print a"), "a = 7");
+
+        // The condition stays true, so it doesn't fire again.
+        assert_eq!(run(&mut debugger, "watch d"), "3: watch d");
+        assert_eq!(run(&mut debugger, "info"), "2: break if a >= 7\n3: watch d");
+        assert_eq!(run(&mut debugger, "continue"), "watchpoint 3: d 2 -> 1\n7: jnz d -5");
+
+        assert_eq!(run(&mut debugger, "delete 3"), "deleted 3: watch d");
+        assert_eq!(run(&mut debugger, "continue"), "halted\nhalted at 9 after 51 steps");
+        assert_eq!(run(&mut debugger, "queues"), "inbox: []\noutbox: [12]");
+        assert_eq!(run(&mut debugger, "step"), "halted");
+    }
+
+    #[test]
+    fn edit_registers() {
+        let mut debugger = multiply();
+        run(&mut debugger, "step 3");
+        assert_eq!(run(&mut debugger, "set c 1"), "c = 1");
+        assert_eq!(run(&mut debugger, "set d 1"), "d = 1");
+        run(&mut debugger, "continue");
+        assert_eq!(run(&mut debugger, "print a"), "a = 1");
+
+        *debugger.machine_mut().registers_mut() = RegisterHolder::with_word(8, Overflow::Checked);
+        assert_eq!(run(&mut debugger, "set a 300"), "can't set a to 300: overflow");
+        assert_eq!(run(&mut debugger, "print a"), "a = 0");
+    }
+
+    #[test]
+    fn blocked() {
+        let mut debugger : Debugger = Debugger::new(Program::load("rcv a\nadd a 1"));
+        assert_eq!(debugger.execute(&Command::Continue), "blocked on receive with an empty inbox");
+        assert_eq!(debugger.execute(&Command::Push(5)), "inbox has 1 values");
+        assert_eq!(debugger.execute(&Command::Continue), "halted\nhalted at 2 after 2 steps");
+        assert_eq!(debugger.execute(&Command::Print(Some('a'))), "a = 6");
+    }
+}
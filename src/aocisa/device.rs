@@ -0,0 +1,499 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+instruction_set! {
+    // The device instruction set from 2018 days 16, 19 and 21, with numbered registers. Each
+    // opcode ending in r takes its second operand from a register, and each ending in i takes it
+    // as a value; gtir and eqir have a value first. Every instruction writes to register c.
+    pub enum Device {
+        Addr = "addr" (a : usize, b : usize, c : usize) => |r| Effect::write(*c, r.add(*r.get_reg(*a), *r.get_reg(*b))),
+        Addi = "addi" (a : usize, b : i64, c : usize) => |r| Effect::write(*c, r.add(*r.get_reg(*a), *b)),
+        Mulr = "mulr" (a : usize, b : usize, c : usize) => |r| Effect::write(*c, r.mul(*r.get_reg(*a), *r.get_reg(*b))),
+        Muli = "muli" (a : usize, b : i64, c : usize) => |r| Effect::write(*c, r.mul(*r.get_reg(*a), *b)),
+        Banr = "banr" (a : usize, b : usize, c : usize) => |r| Effect::Write(*c, r.get_reg(*a) & r.get_reg(*b)),
+        Bani = "bani" (a : usize, b : i64, c : usize) => |r| Effect::Write(*c, r.get_reg(*a) & b),
+        Borr = "borr" (a : usize, b : usize, c : usize) => |r| Effect::Write(*c, r.get_reg(*a) | r.get_reg(*b)),
+        Bori = "bori" (a : usize, b : i64, c : usize) => |r| Effect::Write(*c, r.get_reg(*a) | b),
+        Setr = "setr" (a : usize, b : i64, c : usize) => |r| Effect::Write(*c, *r.get_reg(*a)),
+        Seti = "seti" (a : i64, b : i64, c : usize) => |r| Effect::Write(*c, *a),
+        Gtir = "gtir" (a : i64, b : usize, c : usize) => |r| Effect::Write(*c, (*a > *r.get_reg(*b)) as i64),
+        Gtri = "gtri" (a : usize, b : i64, c : usize) => |r| Effect::Write(*c, (*r.get_reg(*a) > *b) as i64),
+        Gtrr = "gtrr" (a : usize, b : usize, c : usize) => |r| Effect::Write(*c, (r.get_reg(*a) > r.get_reg(*b)) as i64),
+        Eqir = "eqir" (a : i64, b : usize, c : usize) => |r| Effect::Write(*c, (*a == *r.get_reg(*b)) as i64),
+        Eqri = "eqri" (a : usize, b : i64, c : usize) => |r| Effect::Write(*c, (*r.get_reg(*a) == *b) as i64),
+        Eqrr = "eqrr" (a : usize, b : usize, c : usize) => |r| Effect::Write(*c, (r.get_reg(*a) == r.get_reg(*b)) as i64),
+    }
+    registers = usize;
+}
+
+// Each opcode's name, in the same order as Opcode::ALL.
+const OPCODE_NAMES : [&str ; 16] = [
+    "addr", "addi", "mulr", "muli", "banr", "bani", "borr", "bori",
+    "setr", "seti", "gtir", "gtri", "gtrr", "eqir", "eqri", "eqrr",
+];
+
+// The device's opcodes without operands, for working out which number means which.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Opcode {
+    Addr, Addi, Mulr, Muli, Banr, Bani, Borr, Bori,
+    Setr, Seti, Gtir, Gtri, Gtrr, Eqir, Eqri, Eqrr,
+}
+
+// A "before, instruction, after" example from 2018 day 16, with the instruction still numeric:
+// opcode number, a, b, c.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Sample {
+    pub before : Vec<i64>,
+    pub instruction : [i64 ; 4],
+    pub after : Vec<i64>,
+}
+
+// Which opcodes each opcode number could be, narrowed down by samples.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OpcodeInference {
+    possible : BTreeMap<i64, BTreeSet<Opcode>>,
+}
+
+// A device program with the register its ip is bound to, from a "#ip <reg>" first line.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DeviceProgram {
+    pub ip_register : Option<usize>,
+    pub program : Program<Device>,
+}
+
+impl Opcode {
+    pub const ALL : [Opcode ; 16] = [
+        Opcode::Addr, Opcode::Addi, Opcode::Mulr, Opcode::Muli,
+        Opcode::Banr, Opcode::Bani, Opcode::Borr, Opcode::Bori,
+        Opcode::Setr, Opcode::Seti, Opcode::Gtir, Opcode::Gtri,
+        Opcode::Gtrr, Opcode::Eqir, Opcode::Eqri, Opcode::Eqrr,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        OPCODE_NAMES[*self as usize]
+    }
+
+    // The instruction with these operands, or None if an operand that names a register is
+    // negative.
+    pub fn instruction(&self, a : i64, b : i64, c : i64) -> Option<Device> {
+        let reg = |value : i64| if value >= 0 { Some(value as usize) } else { None };
+        let c = reg(c)?;
+        Some(match *self {
+            Opcode::Addr => Device::Addr(reg(a)?, reg(b)?, c),
+            Opcode::Addi => Device::Addi(reg(a)?, b, c),
+            Opcode::Mulr => Device::Mulr(reg(a)?, reg(b)?, c),
+            Opcode::Muli => Device::Muli(reg(a)?, b, c),
+            Opcode::Banr => Device::Banr(reg(a)?, reg(b)?, c),
+            Opcode::Bani => Device::Bani(reg(a)?, b, c),
+            Opcode::Borr => Device::Borr(reg(a)?, reg(b)?, c),
+            Opcode::Bori => Device::Bori(reg(a)?, b, c),
+            Opcode::Setr => Device::Setr(reg(a)?, b, c),
+            Opcode::Seti => Device::Seti(a, b, c),
+            Opcode::Gtir => Device::Gtir(a, reg(b)?, c),
+            Opcode::Gtri => Device::Gtri(reg(a)?, b, c),
+            Opcode::Gtrr => Device::Gtrr(reg(a)?, reg(b)?, c),
+            Opcode::Eqir => Device::Eqir(a, reg(b)?, c),
+            Opcode::Eqri => Device::Eqri(reg(a)?, b, c),
+            Opcode::Eqrr => Device::Eqrr(reg(a)?, reg(b)?, c),
+        })
+    }
+
+    // Whether a and b name registers rather than being values. c always names one. Worked out from
+    // instruction, which only rejects negative operands where it needs a register.
+    fn reads_registers(&self) -> (bool, bool) {
+        (self.instruction(-1, 0, 0).is_none(), self.instruction(0, -1, 0).is_none())
+    }
+}
+
+impl Device {
+    pub fn kind(&self) -> Opcode {
+        match *self {
+            Device::Addr(..) => Opcode::Addr,
+            Device::Addi(..) => Opcode::Addi,
+            Device::Mulr(..) => Opcode::Mulr,
+            Device::Muli(..) => Opcode::Muli,
+            Device::Banr(..) => Opcode::Banr,
+            Device::Bani(..) => Opcode::Bani,
+            Device::Borr(..) => Opcode::Borr,
+            Device::Bori(..) => Opcode::Bori,
+            Device::Setr(..) => Opcode::Setr,
+            Device::Seti(..) => Opcode::Seti,
+            Device::Gtir(..) => Opcode::Gtir,
+            Device::Gtri(..) => Opcode::Gtri,
+            Device::Gtrr(..) => Opcode::Gtrr,
+            Device::Eqir(..) => Opcode::Eqir,
+            Device::Eqri(..) => Opcode::Eqri,
+            Device::Eqrr(..) => Opcode::Eqrr,
+        }
+    }
+}
+
+impl Sample {
+    // Whether running the instruction as the opcode turns before into after. An opcode can't match
+    // if it would use a register the sample doesn't have.
+    pub fn matches(&self, opcode : Opcode) -> bool {
+        let [_, a, b, c] = self.instruction;
+        let len = self.before.len() as i64;
+        let (a_reg, b_reg) = opcode.reads_registers();
+        let in_range = |value : i64, is_reg : bool| !is_reg || (0 .. len).contains(&value);
+        if !in_range(a, a_reg) || !in_range(b, b_reg) || !in_range(c, true) || self.after.len() != self.before.len() {
+            return false;
+        }
+
+        let instruction = match opcode.instruction(a, b, c) {
+            Some(instruction) => instruction,
+            None => return false,
+        };
+
+        let mut registers : RegisterHolder<usize> = RegisterHolder::new();
+        for (i, value) in self.before.iter().enumerate() {
+            *registers.get_reg_mut(i) = *value;
+        }
+
+        registers.apply_instruction(&instruction);
+        self.after.iter().enumerate().all(|(i, value)| registers.get_reg(i) == value)
+    }
+
+    pub fn candidates(&self) -> BTreeSet<Opcode> {
+        Opcode::ALL.iter().cloned().filter(|opcode| self.matches(*opcode)).collect()
+    }
+}
+
+fn parse_register_list(line : &str, line_number : usize, label : &str) -> Result<Vec<i64>, ParseError> {
+    let expected = format!("{} [<values>]", label);
+    let error = |column : usize, token : &str| ParseError::new(column, token, &[expected.as_str()]).at_line(line_number);
+    let rest = match line.strip_prefix(label) {
+        Some(rest) => rest,
+        None => return Err(error(1, line.split_whitespace().next().unwrap_or(""))),
+    };
+
+    let list = rest.trim();
+    let column = line.len() - rest.trim_start().len() + 1;
+    match list.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        Some(values) => values.split(',').map(|v| v.trim().parse().map_err(|_| error(column, list))).collect(),
+        None => Err(error(column, list)),
+    }
+}
+
+fn parse_numeric(line : &str, line_number : usize) -> Result<[i64 ; 4], ParseError> {
+    let mut instruction = [0 ; 4];
+    let tokens = tokenize(line);
+    for (i, slot) in instruction.iter_mut().enumerate() {
+        let token = tokens.get(i).ok_or_else(|| ParseError::new(line.len() + 1, "", &["<opcode#> <a> <b> <c>"]).at_line(line_number))?;
+        *slot = token.text.parse().map_err(|_| ParseError::new(token.column, token.text, &["<number>"]).at_line(line_number))?;
+    }
+
+    match tokens.get(4) {
+        Some(extra) => Err(ParseError::new(extra.column, extra.text, &["end of line"]).at_line(line_number)),
+        None => Ok(instruction),
+    }
+}
+
+// Parses a 2018 day 16 input: samples of "Before:", a numeric instruction and "After:", each
+// separated by a blank line, followed by a numeric program.
+pub fn parse_device_input(input : &str) -> Result<(Vec<Sample>, Vec<[i64 ; 4]>), ParseError> {
+    let mut samples = vec![];
+    let mut program = vec![];
+    let mut lines = input.lines().enumerate().map(|(i, line)| (i + 1, line)).filter(|&(_, line)| !line.trim().is_empty());
+    while let Some((number, line)) = lines.next() {
+        if line.starts_with("Before:") {
+            let before = parse_register_list(line, number, "Before:")?;
+            let (number, line) = lines.next().ok_or_else(|| ParseError::new(1, "", &["<opcode#> <a> <b> <c>"]).at_line(number + 1))?;
+            let instruction = parse_numeric(line, number)?;
+            let (number, line) = lines.next().ok_or_else(|| ParseError::new(1, "", &["After: [<values>]"]).at_line(number + 1))?;
+            let after = parse_register_list(line, number, "After:")?;
+            samples.push(Sample { before, instruction, after });
+        } else {
+            program.push(parse_numeric(line, number)?);
+        }
+    }
+
+    Ok((samples, program))
+}
+
+impl OpcodeInference {
+    // Every number from 0 to 15 could be any opcode to start with.
+    pub fn new() -> OpcodeInference {
+        OpcodeInference {
+            possible : (0 .. Opcode::ALL.len() as i64).map(|n| (n, Opcode::ALL.iter().cloned().collect())).collect(),
+        }
+    }
+
+    pub fn from_samples(samples : &[Sample]) -> OpcodeInference {
+        let mut inference = OpcodeInference::new();
+        for sample in samples {
+            inference.add_sample(sample);
+        }
+
+        inference.propagate();
+        inference
+    }
+
+    // Rules out every opcode the sample shows its number can't be.
+    pub fn add_sample(&mut self, sample : &Sample) {
+        let candidates = sample.candidates();
+        self.possible.entry(sample.instruction[0]).or_insert_with(|| Opcode::ALL.iter().cloned().collect()).retain(|o| candidates.contains(o));
+    }
+
+    // Narrows the possibilities until nothing changes: a number with only one possible opcode
+    // can't share it with another number, and an opcode that only one number could be must be
+    // that number.
+    pub fn propagate(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let known : Vec<(i64, Opcode)> = self.possible.iter().filter(|&(_, p)| p.len() == 1).map(|(n, p)| (*n, *p.iter().next().unwrap())).collect();
+            for (number, opcode) in known {
+                for (other, possible) in self.possible.iter_mut() {
+                    if *other != number {
+                        changed |= possible.remove(&opcode);
+                    }
+                }
+            }
+
+            for opcode in Opcode::ALL.iter() {
+                let numbers : Vec<i64> = self.possible.iter().filter(|&(_, p)| p.contains(opcode)).map(|(n, _)| *n).collect();
+                if numbers.len() == 1 {
+                    let possible = self.possible.get_mut(&numbers[0]).unwrap();
+                    if possible.len() > 1 {
+                        possible.retain(|o| o == opcode);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn possible(&self, number : i64) -> Option<&BTreeSet<Opcode>> {
+        self.possible.get(&number)
+    }
+
+    // Whether the samples contradict each other, leaving some number with no possible opcode.
+    pub fn is_consistent(&self) -> bool {
+        self.possible.values().all(|p| !p.is_empty())
+    }
+
+    // The opcode for every number, once each has exactly one possibility.
+    pub fn mapping(&self) -> Option<BTreeMap<i64, Opcode>> {
+        self.possible.iter().map(|(n, p)| {
+            if p.len() == 1 {
+                Some((*n, *p.iter().next().unwrap()))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    // Turns a numeric program into instructions. Fails on the first instruction whose number
+    // isn't known or whose operands don't fit its opcode, with its 1-based index as the line.
+    pub fn decode(&self, code : &[[i64 ; 4]]) -> Result<Program<Device>, ParseError> {
+        let mapping = self.mapping().unwrap_or_default();
+        let instructions = code.iter().enumerate().map(|(i, &[number, a, b, c])| {
+            mapping.get(&number).and_then(|opcode| opcode.instruction(a, b, c)).ok_or_else(|| {
+                ParseError::new(1, &number.to_string(), &["a known opcode number with valid operands"]).at_line(i + 1)
+            })
+        }).collect::<Result<Vec<Device>, ParseError>>()?;
+
+        Ok(Program {
+            instructions,
+        })
+    }
+}
+
+impl Default for OpcodeInference {
+    fn default() -> OpcodeInference {
+        OpcodeInference::new()
+    }
+}
+
+impl DeviceProgram {
+    // A machine running the program, with its ip bound if the program says so.
+    pub fn machine(&self) -> Machine<Device> {
+        let mut machine = Machine::new(self.program.clone());
+        if let Some(reg) = self.ip_register {
+            machine.bind_ip(reg);
+        }
+        machine
+    }
+}
+
+impl FromStr for DeviceProgram {
+    type Err = Vec<ParseError>;
+
+    fn from_str(input : &str) -> Result<DeviceProgram, Vec<ParseError>> {
+        let (ip_register, body, skipped) = match input.lines().next() {
+            Some(first) if first.starts_with("#ip") => {
+                let mut tokens = TokenStream::new(first);
+                tokens.next_token(&["#ip"]).map_err(|e| vec![e.at_line(1)])?;
+                let reg = tokens.parse_next(<usize as Operand<usize>>::FORM, <usize as Operand<usize>>::parse_operand).map_err(|e| vec![e.at_line(1)])?;
+                tokens.finish().map_err(|e| vec![e.at_line(1)])?;
+                (Some(reg), input.split_once('\n').map(|(_, rest)| rest).unwrap_or(""), 1)
+            },
+            _ => (None, input, 0),
+        };
+
+        let program = body.parse::<Program<Device>>().map_err(|errors| {
+            errors.into_iter().map(|e| {
+                let line = e.line + skipped;
+                e.at_line(line)
+            }).collect::<Vec<ParseError>>()
+        })?;
+
+        Ok(DeviceProgram {
+            ip_register,
+            program,
+        })
+    }
+}
+
+impl fmt::Display for DeviceProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(reg) = self.ip_register {
+            writeln!(f, "#ip {}", reg)?;
+        }
+
+        write!(f, "{}", self.program)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A tiny random number generator, so the inference test doesn't depend on a crate.
+    fn lcg(state : &mut u64) -> i64 {
+        *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((*state >> 33) % 4) as i64
+    }
+
+    #[test]
+    fn opcodes() {
+        let forms : Vec<String> = Opcode::ALL.iter().map(|o| String::from(o.name())).collect();
+        assert_eq!(forms, Device::forms().iter().map(|f| f[.. 4].to_string()).collect::<Vec<String>>());
+        assert_eq!(Opcode::Gtir.instruction(7, 1, 2), Some(Device::Gtir(7, 1, 2)));
+        assert_eq!(Opcode::Gtir.instruction(7, -1, 2), None);
+        assert_eq!(Device::Eqri(3, 1, 0).kind(), Opcode::Eqri);
+        assert_eq!(Opcode::Setr.reads_registers(), (true, false));
+        assert_eq!(Opcode::Seti.reads_registers(), (false, false));
+        assert_eq!(Opcode::Eqir.reads_registers(), (false, true));
+        assert_eq!(Opcode::Bani.reads_registers(), (true, false));
+        assert_eq!(Opcode::Gtrr.reads_registers(), (true, true));
+
+        for opcode in &Opcode::ALL {
+            let instruction = opcode.instruction(1, 2, 3).unwrap();
+            assert_eq!(instruction.kind(), *opcode);
+            assert_eq!(instruction.opcode(), opcode.name());
+            assert_eq!(format!("{} 1 2 3", opcode.name()).parse::<Device>(), Ok(instruction));
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let (samples, program) = parse_device_input(
+r"Before: [3, 2, 1, 1]
+9 2 1 2
+After:  [3, 2, 2, 1]
+
+
+
+0 1 2 3
+").unwrap();
+
+        assert_eq!(samples, vec![Sample { before : vec![3, 2, 1, 1], instruction : [9, 2, 1, 2], after : vec![3, 2, 2, 1] }]);
+        assert_eq!(program, vec![[0, 1, 2, 3]]);
+        assert_eq!(samples[0].candidates().into_iter().collect::<Vec<Opcode>>(), vec![Opcode::Addi, Opcode::Mulr, Opcode::Seti]);
+
+        assert_eq!(parse_device_input("Before: [3, x]\n"), Err(ParseError::new(9, "[3, x]", &["Before: [<values>]"]).at_line(1)));
+        assert_eq!(parse_device_input("Before: [3]\n1 2 3 q\nAfter: [3]"), Err(ParseError::new(7, "q", &["<number>"]).at_line(2)));
+    }
+
+    #[test]
+    fn inference() {
+        // Numbers the opcodes in a scrambled order, then works it back out from random samples.
+        let secret : Vec<Opcode> = (0 .. 16).map(|n| Opcode::ALL[(n * 7 + 3) % 16]).collect();
+        let mut state = 1;
+        let mut samples = vec![];
+        for _ in 0 .. 400 {
+            let number = (lcg(&mut state) * 4 + lcg(&mut state)) as usize;
+            let before : Vec<i64> = (0 .. 4).map(|_| lcg(&mut state)).collect();
+            let (a, b, c) = (lcg(&mut state), lcg(&mut state), lcg(&mut state));
+            let mut registers : RegisterHolder<usize> = RegisterHolder::new();
+            for (i, value) in before.iter().enumerate() {
+                *registers.get_reg_mut(i) = *value;
+            }
+
+            registers.apply_instruction(&secret[number].instruction(a, b, c).unwrap());
+            let after = (0 .. 4).map(|i| *registers.get_reg(i)).collect();
+            samples.push(Sample { before, instruction : [number as i64, a, b, c], after });
+        }
+
+        let mut inference = OpcodeInference::new();
+        inference.add_sample(&samples[0]);
+        assert!(inference.mapping().is_none());
+
+        let inference = OpcodeInference::from_samples(&samples);
+        assert!(inference.is_consistent());
+        let mapping = inference.mapping().unwrap();
+        assert_eq!(mapping.values().cloned().collect::<Vec<Opcode>>(), secret);
+
+        let program = inference.decode(&[[3, 5, 0, 1], [99, 0, 0, 0]]);
+        assert_eq!(program, Err(ParseError::new(1, "99", &["a known opcode number with valid operands"]).at_line(2)));
+        assert_eq!(format!("{}", inference.decode(&[[3, 5, 0, 1]]).unwrap()), format!("{} 5 0 1\n", secret[3].name()));
+    }
+
+    #[test]
+    fn bound_ip() {
+        let input =
+r"#ip 0
+seti 5 0 1
+seti 6 0 2
+addi 0 1 0
+addr 1 2 3
+setr 1 0 0
+seti 8 0 4
+seti 9 0 5";
+        let program : DeviceProgram = input.parse().unwrap();
+        assert_eq!(program.ip_register, Some(0));
+        assert_eq!(format!("{}", program).trim(), input);
+
+        let mut machine = Machine::with_tracer(program.program.clone(), RingBufferTracer::new(10));
+        machine.bind_ip(0);
+        assert_eq!(machine.step(), MachineStatus::Ready);
+        assert_eq!(machine.ip(), 1);
+
+        // Checking the next instruction doesn't leave the ip in its register.
+        assert_eq!(*machine.registers().get_reg(0), 0);
+        assert_eq!(machine.status(), MachineStatus::Ready);
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(machine.steps(), 5);
+        assert_eq!(machine.exit(), None);
+        assert_eq!(machine.registers().values(), vec![(0, 6), (1, 5), (2, 6), (5, 9)]);
+        assert_eq!(machine.tracer().records().map(|r| r.instruction.kind()).collect::<Vec<Opcode>>(),
+                   vec![Opcode::Seti, Opcode::Seti, Opcode::Addi, Opcode::Setr, Opcode::Seti]);
+
+        // The ip has to fit in the register it's bound to.
+        let program : DeviceProgram = "seti 0 0 1\n".repeat(9).parse().unwrap();
+        let mut machine = Machine::new(program.program.clone());
+        *machine.registers_mut() = RegisterHolder::with_word(4, Overflow::Checked);
+        machine.bind_ip(0);
+        assert_eq!(machine.run(), MachineStatus::Faulted(Fault::Overflow));
+        assert_eq!(machine.ip(), 8);
+        assert_eq!(*machine.registers().get_reg(0), 7);
+
+        // Stepping stops at the same place as running.
+        let mut machine = Machine::new(program.program);
+        *machine.registers_mut() = RegisterHolder::with_word(4, Overflow::Checked);
+        machine.bind_ip(0);
+        for _ in 0 .. 7 {
+            assert_eq!(machine.step(), MachineStatus::Ready);
+        }
+
+        assert_eq!(machine.step(), MachineStatus::Faulted(Fault::Overflow));
+        assert_eq!((machine.ip(), *machine.registers().get_reg(0)), (8, 7));
+
+        assert_eq!("#ip x\nseti 1 2 3".parse::<DeviceProgram>(), Err(vec![ParseError::new(5, "x", &["<reg#>"]).at_line(1)]));
+        assert_eq!("#ip 1\nseti 1 2 3\nbad".parse::<DeviceProgram>().map_err(|e| e[0].line), Err(3));
+        assert_eq!("seti 1 2 3".parse::<DeviceProgram>().unwrap().machine().ip_register(), None);
+    }
+}
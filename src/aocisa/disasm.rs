@@ -0,0 +1,360 @@
+use super::*;
+use std::collections::BTreeSet;
+use std::fmt::Write as FmtWrite;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeKind {
+    // Execution continues with the next instruction.
+    FallThrough,
+
+    // A jump was taken.
+    Jump,
+}
+
+// Where an edge in the control-flow graph goes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    // The index of a basic block.
+    Block(usize),
+
+    // Off either end of the program, which halts it.
+    Exit,
+
+    // A jump whose offset is in a register, so it could go anywhere.
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Edge {
+    pub from : usize,
+    pub to : Target,
+    pub kind : EdgeKind,
+}
+
+// The basic blocks of a program and how control moves between them, as far as the jumps with
+// constant offsets tell.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ControlFlowGraph {
+    blocks : Vec<BasicBlock>,
+    edges : Vec<Edge>,
+}
+
+// A program printed with instruction numbers, labels on jump targets, a gutter with arrows for
+// back-edges, and a line between basic blocks.
+pub struct Listing<'p, I>
+where I : 'p {
+    program : &'p Program<I>,
+    cfg : ControlFlowGraph,
+}
+
+impl Edge {
+    // Whether the edge goes backwards, which is what makes a loop.
+    pub fn is_back_edge(&self) -> bool {
+        match self.to {
+            Target::Block(to) => self.kind == EdgeKind::Jump && to <= self.from,
+            _ => false,
+        }
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn new<I>(program : &Program<I>) -> ControlFlowGraph
+    where I : Analyze {
+        let blocks = basic_blocks(program);
+        let mut cfg = ControlFlowGraph {
+            blocks,
+            edges : vec![],
+        };
+
+        for (i, block) in cfg.blocks.iter().enumerate() {
+            let last = block.end - 1;
+            let op = program.instructions[last].op();
+            if let Op::Jump(ref cond, ref offset) = op {
+                if cond.constant() != Some(false) {
+                    let to = match *offset {
+                        RegisterOrValue::Val(offset) => cfg.target_of(offset_ip(last, offset)),
+                        RegisterOrValue::Reg(_) => Target::Unknown,
+                    };
+
+                    cfg.edges.push(Edge { from : i, to, kind : EdgeKind::Jump });
+                }
+            }
+
+            if op.falls_through() {
+                cfg.edges.push(Edge { from : i, to : cfg.target_of(block.end), kind : EdgeKind::FallThrough });
+            }
+        }
+
+        cfg
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    // The index of the block containing the instruction.
+    pub fn block_at(&self, ip : usize) -> Option<usize> {
+        self.blocks.iter().position(|b| b.start <= ip && ip < b.end)
+    }
+
+    fn target_of(&self, ip : usize) -> Target {
+        self.block_at(ip).map(Target::Block).unwrap_or(Target::Exit)
+    }
+
+    // The graph in Graphviz format, with each block's instructions in its node.
+    pub fn to_dot<I>(&self, program : &Program<I>) -> String
+    where I : fmt::Display {
+        let mut dot = String::from("digraph program {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for ip in block.start .. block.end {
+                write!(label, "{}: {}\\l", ip, escape_dot(&program.instructions[ip].to_string())).unwrap();
+            }
+
+            writeln!(dot, "    b{} [label=\"{}\"];", i, label).unwrap();
+        }
+
+        if self.edges.iter().any(|e| e.to == Target::Exit) {
+            dot.push_str("    exit [shape=oval];\n");
+        }
+
+        if self.edges.iter().any(|e| e.to == Target::Unknown) {
+            dot.push_str("    unknown [shape=oval, label=\"?\"];\n");
+        }
+
+        for edge in &self.edges {
+            let to = match edge.to {
+                Target::Block(b) => format!("b{}", b),
+                Target::Exit => String::from("exit"),
+                Target::Unknown => String::from("unknown"),
+            };
+
+            let attributes = if edge.is_back_edge() {
+                " [label=\"jump\", color=blue]"
+            } else if edge.to == Target::Unknown {
+                " [label=\"jump\", style=dotted]"
+            } else if edge.kind == EdgeKind::Jump {
+                " [label=\"jump\"]"
+            } else {
+                ""
+            };
+
+            writeln!(dot, "    b{} -> {}{};", edge.from, to, attributes).unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape_dot(text : &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<I> Program<I>
+where I : Analyze {
+    pub fn control_flow_graph(&self) -> ControlFlowGraph {
+        ControlFlowGraph::new(self)
+    }
+
+    pub fn listing(&self) -> Listing<'_, I> {
+        Listing {
+            program : self,
+            cfg : self.control_flow_graph(),
+        }
+    }
+}
+
+impl<'p, I> Listing<'p, I>
+where I : Analyze {
+    pub fn cfg(&self) -> &ControlFlowGraph {
+        &self.cfg
+    }
+
+    // Each back-edge as (target, source), with a lane for drawing its arrow in. Shorter loops get
+    // lanes closer to the code, so nested loops draw inside each other.
+    fn back_edge_lanes(&self) -> Vec<(usize, usize, usize)> {
+        let mut back_edges : Vec<(usize, usize)> = self.program.instructions.iter().enumerate().filter_map(|(ip, instruction)| {
+            match instruction.op().jump_target(ip) {
+                Some(target) if target <= ip => Some((target, ip)),
+                _ => None,
+            }
+        }).collect();
+        back_edges.sort_by_key(|&(target, source)| source - target);
+
+        let mut lanes : Vec<(usize, usize, usize)> = vec![];
+        for (target, source) in back_edges {
+            let lane = (0 ..).find(|lane| {
+                !lanes.iter().any(|&(t, s, l)| l == *lane && t <= source && target <= s)
+            }).unwrap();
+            lanes.push((target, source, lane));
+        }
+
+        lanes
+    }
+}
+
+// Draws the back-edge lanes for one row. Outer lanes are to the left. at is Some(ip) for an
+// instruction's row, or None for the separator line above the instruction at next_ip.
+fn gutter(lanes : &[(usize, usize, usize)], width : usize, at : Option<usize>, next_ip : usize) -> String {
+    let mut columns = vec![' ' ; width];
+    let mut marker = ' ';
+    for &(target, source, lane) in lanes {
+        let column = width - 1 - lane;
+        match at {
+            Some(ip) if ip == target || ip == source => {
+                columns[column] = '+';
+                for c in &mut columns[column + 1 ..] {
+                    if *c == ' ' {
+                        *c = '-';
+                    }
+                }
+
+                if ip == target {
+                    marker = '>';
+                } else if marker == ' ' {
+                    marker = '-';
+                }
+            },
+            Some(ip) if target < ip && ip < source => columns[column] = '|',
+            None if target < next_ip && next_ip <= source => columns[column] = '|',
+            _ => {},
+        }
+    }
+
+    let mut ret : String = columns.into_iter().collect();
+    if width > 0 {
+        ret.push(marker);
+    }
+    ret
+}
+
+impl<'p, I> fmt::Display for Listing<'p, I>
+where I : Analyze + fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = self.program.instructions.len();
+        let targets : BTreeSet<usize> = self.program.instructions.iter().enumerate().filter_map(|(ip, instruction)| {
+            instruction.op().jump_target(ip).filter(|target| *target < len)
+        }).collect();
+
+        let lanes = self.back_edge_lanes();
+        let width = lanes.iter().map(|&(_, _, lane)| lane + 1).max().unwrap_or(0);
+        let number_width = len.saturating_sub(1).to_string().len();
+        let label_width = targets.iter().map(|t| format!("L{}:", t).len()).max().unwrap_or(0);
+        let text : Vec<String> = self.program.instructions.iter().map(|i| i.to_string()).collect();
+        let text_width = text.iter().map(|t| t.len()).max().unwrap_or(0);
+
+        for (i, block) in self.cfg.blocks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f, "{:>nw$} {} ---- block {}", "", gutter(&lanes, width, None, block.start), i, nw = number_width)?;
+            }
+
+            for (ip, line) in (block.start .. block.end).zip(&text[block.start .. block.end]) {
+                let label = if targets.contains(&ip) { format!("L{}:", ip) } else { String::new() };
+                write!(f, "{:>nw$} {} {:<lw$} ", ip, gutter(&lanes, width, Some(ip), ip), label, nw = number_width, lw = label_width)?;
+
+                let op = self.program.instructions[ip].op();
+                let comment = match op {
+                    Op::Jump(ref cond, ref offset) if cond.constant() != Some(false) => {
+                        match op.jump_target(ip) {
+                            Some(target) if target < len => format!("L{}", target),
+                            Some(_) => String::from("exit"),
+                            None => format!("{}?", offset),
+                        }
+                    },
+                    _ => String::new(),
+                };
+
+                if comment.is_empty() {
+                    writeln!(f, "{}", line)?;
+                } else {
+                    writeln!(f, "{:<tw$}  ; -> {}", line, comment, tw = text_width)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn multiply() -> Program<Assembunny> {
+        r"cpy 7 a
+cpy 5 b
+cpy 3 d
+cpy b c
+inc a
+dec c
+jnz c -2
+dec d
+jnz d -5
+jnz 1 3
+jnz a b".parse().unwrap()
+    }
+
+    #[test]
+    fn edges() {
+        let cfg = multiply().control_flow_graph();
+        assert_eq!(cfg.blocks().len(), 6);
+        assert_eq!(cfg.block_at(5), Some(2));
+        assert_eq!(cfg.edges(), &[
+            Edge { from : 0, to : Target::Block(1), kind : EdgeKind::FallThrough },
+            Edge { from : 1, to : Target::Block(2), kind : EdgeKind::FallThrough },
+            Edge { from : 2, to : Target::Block(2), kind : EdgeKind::Jump },
+            Edge { from : 2, to : Target::Block(3), kind : EdgeKind::FallThrough },
+            Edge { from : 3, to : Target::Block(1), kind : EdgeKind::Jump },
+            Edge { from : 3, to : Target::Block(4), kind : EdgeKind::FallThrough },
+            Edge { from : 4, to : Target::Exit, kind : EdgeKind::Jump },
+            Edge { from : 5, to : Target::Unknown, kind : EdgeKind::Jump },
+            Edge { from : 5, to : Target::Exit, kind : EdgeKind::FallThrough },
+        ][..]);
+
+        let back_edges : Vec<usize> = cfg.edges().iter().filter(|e| e.is_back_edge()).map(|e| e.from).collect();
+        assert_eq!(back_edges, vec![2, 3]);
+    }
+
+    #[test]
+    fn listing() {
+        assert_eq!(format!("{}", multiply().listing()),
+r" 0         cpy 7 a
+ 1         cpy 5 b
+ 2         cpy 3 d
+       ---- block 1
+ 3 +-> L3: cpy b c
+   |   ---- block 2
+ 4 |+> L4: inc a
+ 5 ||      dec c
+ 6 |+-     jnz c -2  ; -> L4
+   |   ---- block 3
+ 7 |       dec d
+ 8 +--     jnz d -5  ; -> L3
+       ---- block 4
+ 9         jnz 1 3   ; -> exit
+       ---- block 5
+10         jnz a b   ; -> b?
+");
+    }
+
+    #[test]
+    fn dot() {
+        let program = Program::load("set a 2\nsub a 1\njgz a -1");
+        assert_eq!(program.control_flow_graph().to_dot(&program),
+r#"digraph program {
+    node [shape=box, fontname="monospace"];
+    b0 [label="0: set a 2\l"];
+    b1 [label="1: sub a 1\l2: jgz a -1\l"];
+    exit [shape=oval];
+    b0 -> b1;
+    b1 -> b1 [label="jump", color=blue];
+    b1 -> exit;
+}
+"#);
+    }
+}
@@ -0,0 +1,160 @@
+use super::*;
+
+// Something that stops an instruction from running.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Fault {
+    DivideByZero,
+
+    // A result didn't fit in checked registers.
+    Overflow,
+}
+
+// Why Machine::execute stopped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    // Ran off the end of the program, or jumped to exactly the end.
+    Halted,
+
+    // Jumped to this index, which is before the start of the program.
+    JumpedBeforeStart(i64),
+
+    // Jumped to this index, which is past the end of the program.
+    JumpedPastEnd(i64),
+
+    // The instruction at the ip couldn't run.
+    Faulted(Fault),
+
+    // Waiting to receive with nothing in the inbox.
+    Blocked,
+
+    // Ran the maximum number of steps without stopping for any other reason.
+    StepLimit,
+}
+
+impl<I, T> Machine<I, T>
+where I : InstructionSet,
+      T : Tracer<I> {
+    // Runs at most max_steps instructions, telling apart all the ways a machine can stop.
+    pub fn execute(&mut self, max_steps : u64) -> Outcome {
+        for _ in 0 .. max_steps {
+            match self.step() {
+                MachineStatus::Ready => {},
+                status => return self.outcome(status),
+            }
+        }
+
+        match self.status() {
+            MachineStatus::Ready => Outcome::StepLimit,
+            status => self.outcome(status),
+        }
+    }
+
+    fn outcome(&self, status : MachineStatus) -> Outcome {
+        match status {
+            MachineStatus::Halted => {
+                match self.exit() {
+                    Some(to) if to < 0 => Outcome::JumpedBeforeStart(to),
+                    Some(to) => Outcome::JumpedPastEnd(to),
+                    None => Outcome::Halted,
+                }
+            },
+            MachineStatus::Faulted(fault) => Outcome::Faulted(fault),
+            MachineStatus::Blocked => Outcome::Blocked,
+            MachineStatus::Ready => Outcome::StepLimit,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::DivideByZero => write!(f, "divide by zero"),
+            Fault::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(Machine::new(Program::load("set a 1\nadd a 2")).execute(10), Outcome::Halted);
+        assert_eq!(Machine::new(Program::load("set a 1\njgz a 1")).execute(10), Outcome::Halted);
+        assert_eq!(Machine::new(Program::load("set a 1\njgz a -2")).execute(10), Outcome::JumpedBeforeStart(-1));
+        assert_eq!(Machine::new(Program::load("set a 1\njgz a 5")).execute(10), Outcome::JumpedPastEnd(6));
+        assert_eq!(Machine::new(Program::load("snd 1\nrcv a")).execute(10), Outcome::Blocked);
+        assert_eq!(Machine::new(Program::load("add a 1\njgz 1 -1")).execute(10), Outcome::StepLimit);
+
+        // Offsets at the limits of i64 are reported rather than overflowing.
+        assert_eq!(Machine::new(Program::load("set a 1\njgz 1 9223372036854775807")).execute(10), Outcome::JumpedPastEnd(i64::MAX));
+        assert_eq!(Machine::new(Program::load("set a 1\njgz 1 -9223372036854775808")).execute(10), Outcome::JumpedBeforeStart(-9223372036854775807));
+        assert_eq!(Machine::new(Program::load("jgz 1 -9223372036854775808")).run(), MachineStatus::Halted);
+
+        let mut machine = Machine::new(Program::load("set a 9223372036854775807\nmul a 2"));
+        assert_eq!(machine.execute(10), Outcome::Faulted(Fault::Overflow));
+        assert_eq!(*machine.registers().get_reg('a'), i64::MAX);
+
+        let mut machine = Machine::new(Program::load("set a 1\nmod a b\nadd a 1"));
+        assert_eq!(machine.execute(10), Outcome::Faulted(Fault::DivideByZero));
+        assert_eq!(machine.ip(), 1);
+        assert_eq!(machine.steps(), 1);
+
+        // Fixing the cause lets it continue.
+        machine.registers_mut().set_reg('b', 1).unwrap();
+        assert_eq!(machine.execute(10), Outcome::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 1);
+    }
+
+    #[test]
+    fn arithmetic_modes() {
+        let program = Program::load("set a 100\nadd a a\nrcv b");
+        let run = |overflow| {
+            let mut machine = Machine::new(program.clone());
+            *machine.registers_mut() = RegisterHolder::with_word(8, overflow);
+            machine.push_input(1000);
+            (machine.execute(10), *machine.registers().get_reg('a'), *machine.registers().get_reg('b'))
+        };
+
+        assert_eq!(run(Overflow::Checked), (Outcome::Faulted(Fault::Overflow), 100, 0));
+        assert_eq!(run(Overflow::Wrap), (Outcome::Halted, -56, -24));
+        assert_eq!(run(Overflow::Saturate), (Outcome::Halted, 127, 127));
+
+        let mut machine = Machine::new(program);
+        *machine.registers_mut() = RegisterHolder::with_word(16, Overflow::Checked);
+        machine.push_input(100000);
+        assert_eq!(machine.execute(10), Outcome::Faulted(Fault::Overflow));
+        assert_eq!(machine.ip(), 2);
+    }
+
+    #[test]
+    fn optimized_overflow() {
+        // The summarized loop overflows where the step-by-step one does.
+        let program = Program::load("set b 4611686018427387904\nset c 4\nadd a b\nsub c 1\njnz c -2");
+        let mut machine = Machine::new(program.clone());
+        *machine.registers_mut() = RegisterHolder::with_word(64, Overflow::Checked);
+        assert_eq!(machine.execute(100), Outcome::Faulted(Fault::Overflow));
+
+        let mut machine = Machine::new(optimize(&program));
+        *machine.registers_mut() = RegisterHolder::with_word(64, Overflow::Checked);
+        assert_eq!(machine.execute(100), Outcome::Faulted(Fault::Overflow));
+
+        let mut machine = Machine::new(optimize(&program));
+        *machine.registers_mut() = RegisterHolder::with_word(64, Overflow::Wrap);
+        assert_eq!(machine.execute(100), Outcome::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 0);
+    }
+
+    #[test]
+    fn scheduler_faults() {
+        let program = Program::load("snd 0\nrcv a\nmod p a");
+        let machines = (0 .. 2).map(|id| {
+            let mut machine = Machine::new(program.clone());
+            machine.registers_mut().set_reg('p', id).unwrap();
+            machine
+        }).collect();
+
+        assert_eq!(Scheduler::new(machines).run(), SchedulerOutcome::Faulted(1, Fault::DivideByZero));
+    }
+}
@@ -0,0 +1,414 @@
+use super::*;
+
+pub const MAX_OPERANDS : usize = 3;
+
+// What an instruction does when executed. Instructions never modify the machine directly; they
+// only describe the change, and whoever is running them applies it.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Effect<R = char> {
+    // Continue with the next instruction without changing anything.
+    Nop,
+
+    // Store a value into a register.
+    Write(R, i64),
+
+    // A conditional relative jump. None if the condition wasn't met.
+    Jump(Option<i64>),
+
+    // Send a value to whatever is connected to this machine's output.
+    Send(i64),
+
+    // Receive a value into a register, blocking if nothing is available.
+    Receive(R),
+
+    // Replace the instruction at a relative offset with its toggled form.
+    Toggle(i64),
+
+    // Store several values at once, then jump by a relative offset. Produced by fused loops that
+    // do the work of many instructions in one step.
+    Fused(Vec<(R, i64)>, i64),
+
+    // The instruction can't run, like dividing by zero. The machine stops without advancing.
+    Fault(Fault),
+}
+
+// The values of an instruction's operands at the time it ran, kept inline to avoid allocating on
+// every step.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct OperandValues {
+    values : [i64 ; MAX_OPERANDS],
+    len : usize,
+}
+
+// A kind of operand that can appear in an instruction set declared with instruction_set!, for an
+// instruction set whose registers are named by R.
+pub trait Operand<R = char> : Sized {
+    // How the operand is described in parse errors, like "<reg>".
+    const FORM : &'static str;
+
+    fn parse_operand(input : &str) -> Result<Self, ParseError>;
+
+    // The value the operand has when it is read, for tracing.
+    fn value(&self, registers : &RegisterHolder<R>) -> i64;
+}
+
+pub trait InstructionSet : Sized + Clone + fmt::Display + FromStr<Err = ParseError> {
+    // How registers are named. Usually letters.
+    type Register : RegisterName;
+
+    fn opcode(&self) -> &'static str;
+
+    // Every accepted form, like "set <reg> <reg|value>", for parse errors.
+    fn forms() -> Vec<String>;
+
+    fn operand_values(&self, registers : &RegisterHolder<Self::Register>) -> OperandValues;
+
+    fn execute(&self, registers : &RegisterHolder<Self::Register>) -> Effect<Self::Register>;
+
+    fn fmt_step(step : &TraceStep<Self>, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?} => {}", step.instruction, step.operands, step.result)
+    }
+
+    // What this instruction becomes when hit by Effect::Toggle.
+    fn toggled(&self) -> Self {
+        self.clone()
+    }
+
+    // Toggles the instruction at the index, which is known to be inside the program. Instruction
+    // sets whose instructions depend on their neighbours can override this to fix them up.
+    fn toggle_in(instructions : &mut [Self], index : usize) {
+        instructions[index] = instructions[index].toggled();
+    }
+}
+
+impl<R> Effect<R> {
+    // Writes the result of register arithmetic, or faults if it failed.
+    pub fn write(reg : R, value : Result<i64, Fault>) -> Effect<R> {
+        match value {
+            Ok(value) => Effect::Write(reg, value),
+            Err(fault) => Effect::Fault(fault),
+        }
+    }
+}
+
+impl OperandValues {
+    pub fn from_slice(values : &[i64]) -> OperandValues {
+        let mut ret = OperandValues::default();
+        ret.values[.. values.len()].copy_from_slice(values);
+        ret.len = values.len();
+        ret
+    }
+
+    pub fn as_slice(&self) -> &[i64] {
+        &self.values[.. self.len]
+    }
+}
+
+impl Operand for char {
+    const FORM : &'static str = "<reg>";
+
+    fn parse_operand(input : &str) -> Result<char, ParseError> {
+        if let Some(captures) = RE_REGISTER.captures_iter(input).next() {
+            Ok(captures.get(1).unwrap().as_str().chars().next().unwrap())
+        } else {
+            Err(ParseError::new(1, input, &[Self::FORM]))
+        }
+    }
+
+    fn value(&self, registers : &RegisterHolder) -> i64 {
+        *registers.get_reg(*self)
+    }
+}
+
+impl<R> Operand<R> for i64
+where R : RegisterName {
+    const FORM : &'static str = "<value>";
+
+    fn parse_operand(input : &str) -> Result<i64, ParseError> {
+        if let Some(value) = RE_VALUE.captures_iter(input).next().and_then(|c| c.get(1).unwrap().as_str().parse::<i64>().ok()) {
+            Ok(value)
+        } else {
+            Err(ParseError::new(1, input, &[<Self as Operand<R>>::FORM]))
+        }
+    }
+
+    fn value(&self, _registers : &RegisterHolder<R>) -> i64 {
+        *self
+    }
+}
+
+impl Operand for RegisterOrValue {
+    const FORM : &'static str = "<reg|value>";
+
+    fn parse_operand(input : &str) -> Result<RegisterOrValue, ParseError> {
+        if let Ok(reg) = char::parse_operand(input) {
+            Ok(RegisterOrValue::Reg(reg))
+        } else if let Ok(value) = <i64 as Operand>::parse_operand(input) {
+            Ok(RegisterOrValue::Val(value))
+        } else {
+            Err(ParseError::new(1, input, &[Self::FORM]))
+        }
+    }
+
+    fn value(&self, registers : &RegisterHolder) -> i64 {
+        registers.evaluate(self)
+    }
+}
+
+// A numbered register, written either as the bare number or with an "r" in front, like r3.
+impl Operand<usize> for usize {
+    const FORM : &'static str = "<reg#>";
+
+    fn parse_operand(input : &str) -> Result<usize, ParseError> {
+        let digits = input.strip_prefix('r').unwrap_or(input);
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(reg) = digits.parse::<usize>() {
+                return Ok(reg);
+            }
+        }
+
+        Err(ParseError::new(1, input, &[Self::FORM]))
+    }
+
+    fn value(&self, registers : &RegisterHolder<usize>) -> i64 {
+        *registers.get_reg(*self)
+    }
+}
+
+// Applies a relative jump to an instruction pointer. Jumping before the start of the program
+// produces usize::MAX, which is past the end of any program.
+pub fn offset_ip(current_ip : usize, offset : i64) -> usize {
+    if offset >= 0 {
+        current_ip.saturating_add(offset as usize)
+    } else {
+        current_ip.checked_sub(offset.unsigned_abs() as usize).unwrap_or(usize::MAX)
+    }
+}
+
+// Declares an instruction set: an enum with one variant per opcode, plus its parser, Display, and
+// executor. Each instruction lists its operands with their kinds, then a block that computes the
+// instruction's Effect from the current registers:
+//
+// instruction_set! {
+//     pub enum Assembly {
+//         Inc = "inc" (reg : char) => |r| Effect::Write(*reg, r.get_reg(*reg) + 1),
+//     }
+// }
+//
+// Optionally, "registers = type;" after the enum names registers with something other than char,
+// "trace = path;" overrides how steps are formatted when traced, and "toggle = path;" supplies the
+// function used for Effect::Toggle.
+#[macro_export]
+macro_rules! instruction_set {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $(
+                $variant:ident = $opcode:literal ( $( $arg:ident : $kind:ty ),* ) => |$regs:ident| $body:expr
+            ),* $(,)*
+        }
+        $(registers = $register:ty;)?
+        $(trace = $trace:path;)?
+        $(toggle = $toggle:path;)?
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+        pub enum $name {
+            $( $variant( $( $kind ),* ), )*
+        }
+
+        impl $crate::aocisa::InstructionSet for $name {
+            type Register = $crate::__aocisa_register_type!($($register)?);
+
+            fn opcode(&self) -> &'static str {
+                match *self {
+                    $( $name::$variant(..) => $opcode, )*
+                }
+            }
+
+            fn forms() -> Vec<String> {
+                vec![
+                    $(
+                        {
+                            #[allow(unused_mut)]
+                            let mut form = String::from($opcode);
+                            $(
+                                form.push(' ');
+                                form.push_str(<$kind as $crate::aocisa::Operand<Self::Register>>::FORM);
+                            )*
+                            form
+                        },
+                    )*
+                ]
+            }
+
+            #[allow(unused_variables)]
+            fn operand_values(&self, registers : &$crate::aocisa::RegisterHolder<Self::Register>) -> $crate::aocisa::OperandValues {
+                match *self {
+                    $(
+                        $name::$variant( $( ref $arg ),* ) => {
+                            $crate::aocisa::OperandValues::from_slice(&[ $( $crate::aocisa::Operand::<Self::Register>::value($arg, registers) ),* ])
+                        },
+                    )*
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn execute(&self, registers : &$crate::aocisa::RegisterHolder<Self::Register>) -> $crate::aocisa::Effect<Self::Register> {
+                match *self {
+                    $(
+                        $name::$variant( $( ref $arg ),* ) => {
+                            let $regs = registers;
+                            $body
+                        },
+                    )*
+                }
+            }
+
+            $(
+                fn fmt_step(step : &$crate::aocisa::TraceStep<Self>, f : &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    $trace(step, f)
+                }
+            )?
+
+            $(
+                fn toggled(&self) -> Self {
+                    $toggle(self)
+                }
+            )?
+        }
+
+        impl ::std::str::FromStr for $name {
+            type Err = $crate::aocisa::ParseError;
+
+            fn from_str(input : &str) -> Result<$name, $crate::aocisa::ParseError> {
+                let mut tokens = $crate::aocisa::TokenStream::new(input);
+                let opcode = tokens.next_token(&[] as &[&str]).map_err(|e| {
+                    e.expecting(&<$name as $crate::aocisa::InstructionSet>::forms())
+                })?;
+
+                let instruction = match opcode.text {
+                    $(
+                        $opcode => $name::$variant( $(
+                            tokens.parse_next(
+                                <$kind as $crate::aocisa::Operand<<$name as $crate::aocisa::InstructionSet>::Register>>::FORM,
+                                <$kind as $crate::aocisa::Operand<<$name as $crate::aocisa::InstructionSet>::Register>>::parse_operand)?
+                        ),* ),
+                    )*
+                    _ => {
+                        return Err($crate::aocisa::ParseError::new(opcode.column, opcode.text, &<$name as $crate::aocisa::InstructionSet>::forms()));
+                    },
+                };
+
+                tokens.finish()?;
+                Ok(instruction)
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f : &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                match *self {
+                    $(
+                        $name::$variant( $( ref $arg ),* ) => {
+                            write!(f, "{}", $opcode)?;
+                            $( write!(f, " {}", $arg)?; )*
+                            Ok(())
+                        },
+                    )*
+                }
+            }
+        }
+    };
+}
+
+// The register type for instruction_set!, which is char unless the declaration says otherwise.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __aocisa_register_type {
+    () => { char };
+    ($register:ty) => { $register };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    instruction_set! {
+        pub enum Tiny {
+            Inc = "inc" (reg : char) => |r| Effect::Write(*reg, r.get_reg(*reg) + 1),
+            Put = "put" (reg : char, value : i64) => |r| Effect::Write(*reg, *value),
+            Out = "out" (rv : RegisterOrValue) => |r| Effect::Send(r.evaluate(rv)),
+            Hlt = "hlt" () => |r| Effect::Jump(Some(1000)),
+        }
+    }
+
+    instruction_set! {
+        pub enum Numbered {
+            Seti = "seti" (value : i64, dest : usize) => |r| Effect::Write(*dest, *value),
+            Addr = "addr" (a : usize, b : usize, dest : usize) => |r| Effect::write(*dest, r.add(*r.get_reg(*a), *r.get_reg(*b))),
+        }
+        registers = usize;
+    }
+
+    #[test]
+    fn parse_print() {
+        let input =
+r"inc a
+put b -4
+out b
+out 7
+hlt";
+        assert_eq!(input, format!("{}", input.parse::<Program<Tiny>>().unwrap()).trim());
+    }
+
+    #[test]
+    fn forms() {
+        assert_eq!(Tiny::forms(), vec!["inc <reg>", "put <reg> <value>", "out <reg|value>", "hlt"]);
+        assert_eq!(Tiny::Put('a', 1).opcode(), "put");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("put a b".parse::<Tiny>(), Err(ParseError::new(7, "b", &["<value>"])));
+        assert_eq!("hlt 1".parse::<Tiny>(), Err(ParseError::new(5, "1", &["end of line"])));
+        assert_eq!("jmp 1".parse::<Tiny>(), Err(ParseError::new(1, "jmp", &Tiny::forms())));
+        assert_eq!("".parse::<Tiny>(), Err(ParseError::new(1, "", &Tiny::forms())));
+    }
+
+    #[test]
+    fn execute() {
+        let mut registers = RegisterHolder::default();
+        *registers.get_reg_mut('c') = 9;
+        assert_eq!(Tiny::Inc('c').execute(&registers), Effect::Write('c', 10));
+        assert_eq!(Tiny::Put('c', -1).execute(&registers), Effect::Write('c', -1));
+        assert_eq!(Tiny::Out(RegisterOrValue::Reg('c')).execute(&registers), Effect::Send(9));
+        assert_eq!(Tiny::Put('c', -1).operand_values(&registers).as_slice(), &[9, -1]);
+        assert_eq!(Tiny::Hlt().operand_values(&registers).as_slice(), &[] as &[i64]);
+    }
+
+    #[test]
+    fn machine() {
+        let mut machine = Machine::new("put a 3\nout a\ninc a\nout a".parse::<Program<Tiny>>().unwrap());
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(machine.pop_output(), Some(3));
+        assert_eq!(machine.pop_output(), Some(4));
+    }
+
+    #[test]
+    fn numbered_registers() {
+        assert_eq!(Numbered::forms(), vec!["seti <value> <reg#>", "addr <reg#> <reg#> <reg#>"]);
+        assert_eq!("addr r1 x 2".parse::<Numbered>(), Err(ParseError::new(9, "x", &["<reg#>"])));
+
+        let mut machine = Machine::new("seti 5 r0\naddr 0 r0 60".parse::<Program<Numbered>>().unwrap());
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(machine.registers().values(), vec![(0, 5), (60, 10)]);
+        assert_eq!(format!("{}", machine.program()), "seti 5 0\naddr 0 0 60\n");
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(offset_ip(5, 2), 7);
+        assert_eq!(offset_ip(5, -5), 0);
+        assert_eq!(offset_ip(5, -6), usize::MAX);
+    }
+}
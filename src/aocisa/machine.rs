@@ -0,0 +1,854 @@
+use super::*;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MachineStatus {
+    Ready,
+    Blocked,
+    Halted,
+
+    // The next instruction can't run, like a mod by zero. The machine won't advance.
+    Faulted(Fault),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SchedulerOutcome {
+    // Every machine ran off the end of its program.
+    Halted,
+
+    // At least one machine is waiting on rcv and none of the others can make progress.
+    Deadlocked,
+
+    // The machine at the index faulted, which stops everything.
+    Faulted(usize, Fault),
+}
+
+// The output of a machine that has been shown to loop forever: the prefix once, then the cycle
+// repeated indefinitely.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OutputCycle {
+    pub prefix : Vec<i64>,
+    pub cycle : Vec<i64>,
+}
+
+// A machine that got back to a state it was in before, so it will repeat the same steps forever.
+// start is the step count when it was first in that state.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StateCycle {
+    pub start : u64,
+    pub len : u64,
+}
+
+// A single program with its own registers and message queues. Sends append to the outbox and
+// receives pop from the inbox, blocking if it is empty.
+#[derive(Clone)]
+pub struct Machine<I = Instruction, T = NullTracer>
+where I : InstructionSet {
+    program : Rc<Program<I>>,
+    registers : RegisterHolder<I::Register>,
+    ip : usize,
+    inbox : VecDeque<i64>,
+    outbox : VecDeque<i64>,
+    send_count : usize,
+    steps : u64,
+    exit : Option<i64>,
+    ip_register : Option<I::Register>,
+    tracer : T,
+    history : VecDeque<Snapshot<I>>,
+    history_capacity : usize,
+}
+
+// Everything about a machine that changes as it runs, other than its tracer. Snapshots are cheap
+// to take because the program is shared with the machine until something toggles it. Two
+// snapshots are equal if the program, registers, ip and queues are, regardless of how many steps
+// it took to get there.
+#[derive(Clone)]
+pub struct Snapshot<I = Instruction>
+where I : InstructionSet {
+    program : Rc<Program<I>>,
+    registers : RegisterHolder<I::Register>,
+    ip : usize,
+    inbox : VecDeque<i64>,
+    outbox : VecDeque<i64>,
+    send_count : usize,
+    steps : u64,
+    exit : Option<i64>,
+}
+
+// Anything that reads values from an inbox and writes them to an outbox, so that the Scheduler can
+// connect it to others.
+pub trait Process {
+    fn status(&self) -> MachineStatus;
+
+    // Runs until blocked on input or halted.
+    fn run(&mut self) -> MachineStatus;
+
+    fn push_input(&mut self, value : i64);
+    fn pop_output(&mut self) -> Option<i64>;
+
+    // How many instructions have executed, so the Scheduler can tell if anything happened.
+    fn steps(&self) -> u64;
+
+    fn send_count(&self) -> usize;
+}
+
+// Runs several machines round robin, delivering each machine's outbox to the inbox of the machine
+// it is connected to.
+pub struct Scheduler<P = Machine> {
+    machines : Vec<P>,
+    destinations : Vec<usize>,
+}
+
+impl<I> Machine<I>
+where I : InstructionSet {
+    pub fn new(program : Program<I>) -> Machine<I> {
+        Machine::with_tracer(program, NullTracer)
+    }
+}
+
+impl<I, T> Machine<I, T>
+where I : InstructionSet,
+      T : Tracer<I> {
+    pub fn with_tracer(program : Program<I>, tracer : T) -> Machine<I, T> {
+        Machine {
+            program : Rc::new(program),
+            registers : RegisterHolder::default(),
+            ip : 0,
+            inbox : VecDeque::new(),
+            outbox : VecDeque::new(),
+            send_count : 0,
+            steps : 0,
+            exit : None,
+            ip_register : None,
+            tracer,
+            history : VecDeque::new(),
+            history_capacity : 0,
+        }
+    }
+
+    pub fn tracer(&self) -> &T {
+        &self.tracer
+    }
+
+    pub fn tracer_mut(&mut self) -> &mut T {
+        &mut self.tracer
+    }
+
+    pub fn program(&self) -> &Program<I> {
+        &self.program
+    }
+
+    pub fn registers(&self) -> &RegisterHolder<I::Register> {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut RegisterHolder<I::Register> {
+        &mut self.registers
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn send_count(&self) -> usize {
+        self.send_count
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn push_input(&mut self, value : i64) {
+        self.inbox.push_back(value);
+    }
+
+    pub fn pop_output(&mut self) -> Option<i64> {
+        self.outbox.pop_front()
+    }
+
+    pub fn inbox(&self) -> &VecDeque<i64> {
+        &self.inbox
+    }
+
+    pub fn outbox(&self) -> &VecDeque<i64> {
+        &self.outbox
+    }
+
+    pub fn status(&self) -> MachineStatus {
+        match self.ip_register {
+            Some(reg) if self.ip < self.program.instructions.len() => {
+                let mut registers = self.registers.clone();
+                match registers.set_reg(reg, self.ip as i64) {
+                    Ok(()) => self.status_with(&registers),
+                    Err(fault) => MachineStatus::Faulted(fault),
+                }
+            },
+            _ => self.status_with(&self.registers),
+        }
+    }
+
+    // The same as status, but binds the ip in place and puts the register back afterwards, rather
+    // than working on a copy of all the registers. step does this after every instruction.
+    fn next_status(&mut self) -> MachineStatus {
+        let reg = match self.ip_register {
+            Some(reg) if self.ip < self.program.instructions.len() => reg,
+            _ => return self.status_with(&self.registers),
+        };
+
+        let saved = *self.registers.get_reg(reg);
+        if let Err(fault) = self.registers.set_reg(reg, self.ip as i64) {
+            return MachineStatus::Faulted(fault);
+        }
+
+        let status = self.status_with(&self.registers);
+        *self.registers.get_reg_mut(reg) = saved;
+        status
+    }
+
+    // What would happen if the instruction at the ip ran with these registers, with the ip already
+    // bound if it needs to be.
+    fn status_with(&self, registers : &RegisterHolder<I::Register>) -> MachineStatus {
+        match self.program.instructions.get(self.ip) {
+            None => MachineStatus::Halted,
+            Some(instruction) => {
+                let effect = instruction.execute(registers);
+                if let Some(fault) = self.fault(&effect) {
+                    return MachineStatus::Faulted(fault);
+                }
+
+                match effect {
+                    Effect::Receive(_) if self.inbox.is_empty() => MachineStatus::Blocked,
+                    _ => MachineStatus::Ready,
+                }
+            },
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot<I> {
+        Snapshot {
+            program : self.program.clone(),
+            registers : self.registers.clone(),
+            ip : self.ip,
+            inbox : self.inbox.clone(),
+            outbox : self.outbox.clone(),
+            send_count : self.send_count,
+            steps : self.steps,
+            exit : self.exit,
+        }
+    }
+
+    // Puts the machine back the way it was when the snapshot was taken. The tracer and history
+    // are left alone.
+    pub fn restore(&mut self, snapshot : &Snapshot<I>) {
+        self.program = snapshot.program.clone();
+        self.registers = snapshot.registers.clone();
+        self.ip = snapshot.ip;
+        self.inbox = snapshot.inbox.clone();
+        self.outbox = snapshot.outbox.clone();
+        self.send_count = snapshot.send_count;
+        self.steps = snapshot.steps;
+        self.exit = snapshot.exit;
+    }
+
+    // Starts keeping a snapshot before every step, up to the capacity, so that step_back can undo
+    // them. A capacity of 0 turns history off, which is the default.
+    pub fn set_history_capacity(&mut self, capacity : usize) {
+        self.history_capacity = capacity;
+        while self.history.len() > capacity {
+            self.history.pop_front();
+        }
+    }
+
+    // How many steps can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    // Undoes the last step. Returns false if there's no history to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(snapshot) => {
+                self.restore(&snapshot);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Why an effect can't be applied, if it can't: either the instruction faulted, or a value it
+    // writes doesn't fit in checked registers.
+    fn fault(&self, effect : &Effect<I::Register>) -> Option<Fault> {
+        let fits = |value : i64| self.registers.fit(i128::from(value)).is_ok();
+        match *effect {
+            Effect::Fault(fault) => Some(fault),
+            Effect::Write(_, value) if !fits(value) => Some(Fault::Overflow),
+            Effect::Receive(_) if self.inbox.front().is_some_and(|value| !fits(*value)) => Some(Fault::Overflow),
+            Effect::Fused(ref writes, _) if writes.iter().any(|&(_, value)| !fits(value)) => Some(Fault::Overflow),
+            _ => None,
+        }
+    }
+
+    // Binds the ip to a register, like "#ip 3" in 2018's device programs. The register is set to
+    // the ip before each instruction, and the ip is set to the register's value after it, then
+    // moves to the next instruction as usual. Instruction sets that jump shouldn't bind the ip.
+    pub fn bind_ip(&mut self, reg : I::Register) {
+        self.ip_register = Some(reg);
+    }
+
+    pub fn ip_register(&self) -> Option<I::Register> {
+        self.ip_register
+    }
+
+    // Where the last jump went, if it left the program by going before the start or past the end.
+    // Halting by running off the end, or jumping to exactly the end, leaves this None.
+    pub fn exit(&self) -> Option<i64> {
+        self.exit
+    }
+
+    // Executes one instruction if possible. Returns Ready if it did, or else why it couldn't.
+    fn advance(&mut self) -> MachineStatus {
+        let before = if self.history_capacity > 0 {
+            Some(self.snapshot())
+        } else {
+            None
+        };
+
+        let instruction = match self.program.instructions.get(self.ip) {
+            Some(instruction) => instruction,
+            None => return MachineStatus::Halted,
+        };
+
+        // The ip might not fit in narrow registers.
+        if let Some(reg) = self.ip_register {
+            if let Err(fault) = self.registers.set_reg(reg, self.ip as i64) {
+                return MachineStatus::Faulted(fault);
+            }
+        }
+
+        let operands = instruction.operand_values(&self.registers);
+        let effect = instruction.execute(&self.registers);
+        if let Some(fault) = self.fault(&effect) {
+            return MachineStatus::Faulted(fault);
+        }
+
+        let mut next_ip = self.ip + 1;
+        let result = match effect {
+            Effect::Nop => TraceResult::Nothing,
+            Effect::Write(reg, value) => {
+                if let Err(fault) = self.registers.set_reg(reg, value) {
+                    return MachineStatus::Faulted(fault);
+                }
+
+                TraceResult::Value(value)
+            },
+            Effect::Jump(target) => {
+                if let Some(offset) = target {
+                    next_ip = offset_ip(self.ip, offset);
+
+                    // A target too far to represent is still clearly on one side of the program.
+                    let to = (self.ip as i64).checked_add(offset).unwrap_or(if offset < 0 { i64::MIN } else { i64::MAX });
+                    if to < 0 || to > self.program.instructions.len() as i64 {
+                        self.exit = Some(to);
+                    }
+                }
+
+                TraceResult::Branch(target.is_some())
+            },
+            Effect::Send(value) => {
+                self.outbox.push_back(value);
+                self.send_count += 1;
+                TraceResult::Value(value)
+            },
+            Effect::Receive(reg) => {
+                match self.inbox.pop_front() {
+                    // Checked by fault, so the value is never lost.
+                    Some(value) => {
+                        if let Err(fault) = self.registers.set_reg(reg, value) {
+                            return MachineStatus::Faulted(fault);
+                        }
+
+                        TraceResult::Value(value)
+                    },
+                    None => return MachineStatus::Blocked,
+                }
+            },
+            Effect::Toggle(_) => TraceResult::Nothing,
+            Effect::Fused(ref writes, offset) => {
+                // Also checked by fault, so the writes are all or nothing.
+                for &(reg, value) in writes {
+                    if let Err(fault) = self.registers.set_reg(reg, value) {
+                        return MachineStatus::Faulted(fault);
+                    }
+                }
+
+                next_ip = offset_ip(self.ip, offset);
+                TraceResult::Nothing
+            },
+            Effect::Fault(fault) => return MachineStatus::Faulted(fault),
+        };
+
+        self.tracer.trace(&TraceStep {
+            instruction,
+            operands : operands.as_slice(),
+            result,
+        });
+
+        if let Effect::Toggle(offset) = effect {
+            let target = offset_ip(self.ip, offset);
+            if target < self.program.instructions.len() {
+                Rc::make_mut(&mut self.program).toggle(target);
+            }
+        }
+
+        if let Some(snapshot) = before {
+            if self.history.len() == self.history_capacity {
+                self.history.pop_front();
+            }
+
+            self.history.push_back(snapshot);
+        }
+
+        if let Some(reg) = self.ip_register {
+            let to = self.registers.get_reg(reg).checked_add(1).unwrap_or(i64::MAX);
+            next_ip = if to < 0 { usize::MAX } else { to as usize };
+            if to < 0 || to > self.program.instructions.len() as i64 {
+                self.exit = Some(to);
+            }
+        }
+
+        self.ip = next_ip;
+        self.steps += 1;
+        MachineStatus::Ready
+    }
+
+    // Executes at most one instruction. Doesn't advance if the machine is blocked or halted.
+    pub fn step(&mut self) -> MachineStatus {
+        match self.advance() {
+            MachineStatus::Ready => self.next_status(),
+            status => status,
+        }
+    }
+
+    // Steps until the machine blocks on a receive or halts.
+    pub fn run(&mut self) -> MachineStatus {
+        loop {
+            let status = self.advance();
+            if status != MachineStatus::Ready {
+                return status;
+            }
+        }
+    }
+}
+
+impl<I, T> Machine<I, T>
+where I : InstructionSet + Hash + Eq,
+      T : Tracer<I> {
+    // Runs until the machine is back in a state it was in right after an earlier output, which
+    // means the output between those two points repeats forever. Gives up if the machine halts,
+    // blocks, or runs for max_steps without repeating.
+    pub fn find_output_cycle(&mut self, max_steps : u64) -> Option<OutputCycle> {
+        let mut outputs = vec![];
+        let mut seen = HashMap::new();
+        let last_step = self.steps + max_steps;
+
+        while self.steps < last_step {
+            if self.advance() != MachineStatus::Ready {
+                return None;
+            }
+
+            if let Some(value) = self.pop_output() {
+                outputs.push(value);
+
+                if let Some(previous) = seen.insert(self.snapshot(), outputs.len()) {
+                    let cycle = outputs.split_off(previous);
+                    return Some(OutputCycle {
+                        prefix : outputs,
+                        cycle,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    // Runs until the machine is in exactly the same state as it was at some earlier step, which
+    // means it will loop forever. Output that nothing collects counts as part of the state, so a
+    // machine that keeps sending won't be caught unless something drains its outbox. Gives up if
+    // the machine halts, blocks, or runs for max_steps without repeating.
+    pub fn find_cycle(&mut self, max_steps : u64) -> Option<StateCycle> {
+        let mut seen = HashMap::new();
+        let last_step = self.steps + max_steps;
+        seen.insert(self.snapshot(), self.steps);
+
+        while self.steps < last_step {
+            if self.advance() != MachineStatus::Ready {
+                return None;
+            }
+
+            if let Some(start) = seen.insert(self.snapshot(), self.steps) {
+                return Some(StateCycle {
+                    start,
+                    len : self.steps - start,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl<I> Snapshot<I>
+where I : InstructionSet {
+    pub fn program(&self) -> &Program<I> {
+        &self.program
+    }
+
+    pub fn registers(&self) -> &RegisterHolder<I::Register> {
+        &self.registers
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn inbox(&self) -> &VecDeque<i64> {
+        &self.inbox
+    }
+
+    pub fn outbox(&self) -> &VecDeque<i64> {
+        &self.outbox
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+impl<I> PartialEq for Snapshot<I>
+where I : InstructionSet + PartialEq {
+    fn eq(&self, other : &Snapshot<I>) -> bool {
+        self.ip == other.ip &&
+            self.registers == other.registers &&
+            self.inbox == other.inbox &&
+            self.outbox == other.outbox &&
+            (Rc::ptr_eq(&self.program, &other.program) || self.program == other.program)
+    }
+}
+
+impl<I> Eq for Snapshot<I>
+where I : InstructionSet + Eq {
+}
+
+impl<I> Hash for Snapshot<I>
+where I : InstructionSet + Hash {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.ip.hash(state);
+        self.registers.hash(state);
+        self.inbox.hash(state);
+        self.outbox.hash(state);
+        self.program.hash(state);
+    }
+}
+
+impl OutputCycle {
+    // Whether the output is 0, 1, 0, 1... forever, as 2016 day 25 wants.
+    pub fn is_clock_signal(&self) -> bool {
+        !self.cycle.is_empty() &&
+            self.prefix.iter().chain(self.cycle.iter()).chain(self.cycle.iter()).enumerate().all(|(i, v)| *v == (i % 2) as i64)
+    }
+}
+
+impl<I, T> Process for Machine<I, T>
+where I : InstructionSet,
+      T : Tracer<I> {
+    fn status(&self) -> MachineStatus {
+        Machine::status(self)
+    }
+
+    fn run(&mut self) -> MachineStatus {
+        Machine::run(self)
+    }
+
+    fn push_input(&mut self, value : i64) {
+        Machine::push_input(self, value)
+    }
+
+    fn pop_output(&mut self) -> Option<i64> {
+        Machine::pop_output(self)
+    }
+
+    fn steps(&self) -> u64 {
+        Machine::steps(self)
+    }
+
+    fn send_count(&self) -> usize {
+        Machine::send_count(self)
+    }
+}
+
+impl<P> Scheduler<P>
+where P : Process {
+    // By default the machines are connected in a ring: each one sends to the next, and the last
+    // one sends to the first. With two machines, that is the duet from 2017 day 18.
+    pub fn new(machines : Vec<P>) -> Scheduler<P> {
+        let count = machines.len();
+        Scheduler {
+            machines,
+            destinations : (0 .. count).map(|i| (i + 1) % count).collect(),
+        }
+    }
+
+    pub fn connect(&mut self, from : usize, to : usize) {
+        if to < self.machines.len() {
+            self.destinations[from] = to;
+        } else {
+            panic!("no machine {} to connect to. only {} machines", to, self.machines.len());
+        }
+    }
+
+    pub fn machines(&self) -> &[P] {
+        &self.machines
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [P] {
+        &mut self.machines
+    }
+
+    pub fn send_counts(&self) -> Vec<usize> {
+        self.machines.iter().map(|m| m.send_count()).collect()
+    }
+
+    fn deliver_outputs(&mut self, from : usize) {
+        let to = self.destinations[from];
+        while let Some(value) = self.machines[from].pop_output() {
+            self.machines[to].push_input(value);
+        }
+    }
+
+    // Runs every machine in turn until none of them can make progress.
+    pub fn run(&mut self) -> SchedulerOutcome {
+        loop {
+            let mut made_progress = false;
+            for i in 0 .. self.machines.len() {
+                let steps_before = self.machines[i].steps();
+                if let MachineStatus::Faulted(fault) = self.machines[i].run() {
+                    return SchedulerOutcome::Faulted(i, fault);
+                }
+
+                made_progress |= self.machines[i].steps() != steps_before;
+                self.deliver_outputs(i);
+            }
+
+            if !made_progress {
+                break;
+            }
+        }
+
+        if self.machines.iter().all(|m| m.status() == MachineStatus::Halted) {
+            SchedulerOutcome::Halted
+        } else {
+            SchedulerOutcome::Deadlocked
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn duet(input : &str) -> Scheduler {
+        let program = Program::load(input);
+        let machines = (0 .. 2).map(|id| {
+            let mut machine = Machine::new(program.clone());
+            *machine.registers_mut().get_reg_mut('p') = id;
+            machine
+        }).collect();
+
+        Scheduler::new(machines)
+    }
+
+    #[test]
+    fn send_receive() {
+        let mut machine = Machine::new(Program::load(
+r"snd 5
+rcv a
+add a 1
+snd a"));
+
+        assert_eq!(machine.run(), MachineStatus::Blocked);
+        assert_eq!(machine.pop_output(), Some(5));
+        assert_eq!(machine.step(), MachineStatus::Blocked);
+
+        machine.push_input(10);
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(machine.pop_output(), Some(11));
+        assert_eq!(machine.pop_output(), None);
+        assert_eq!(machine.send_count(), 2);
+    }
+
+    #[test]
+    fn jump_out_halts() {
+        let mut machine = Machine::new(Program::load(
+r"set a 1
+jgz a -5"));
+
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(machine.steps(), 2);
+    }
+
+    #[test]
+    fn duet_deadlock() {
+        let mut scheduler = duet(
+r"snd 1
+snd 2
+snd p
+rcv a
+rcv b
+rcv c
+rcv d");
+
+        assert_eq!(scheduler.run(), SchedulerOutcome::Deadlocked);
+        assert_eq!(scheduler.send_counts(), vec![3, 3]);
+        assert_eq!(*scheduler.machines()[0].registers().get_reg('c'), 1);
+        assert_eq!(*scheduler.machines()[1].registers().get_reg('c'), 0);
+    }
+
+    #[test]
+    fn duet_halts() {
+        let mut scheduler = duet(
+r"snd p
+rcv a");
+
+        assert_eq!(scheduler.run(), SchedulerOutcome::Halted);
+        assert_eq!(scheduler.send_counts(), vec![1, 1]);
+        assert_eq!(*scheduler.machines()[0].registers().get_reg('a'), 1);
+        assert_eq!(*scheduler.machines()[1].registers().get_reg('a'), 0);
+    }
+
+    #[test]
+    fn ring_of_three() {
+        // Each machine adds one to what it receives and passes it on; the first one seeds it.
+        let program = Program::load(
+r"jgz p 2
+snd 0
+rcv a
+add a 1
+snd a");
+
+        let machines = (0 .. 3).map(|id| {
+            let mut machine = Machine::new(program.clone());
+            *machine.registers_mut().get_reg_mut('p') = id;
+            machine
+        }).collect();
+
+        let mut scheduler = Scheduler::new(machines);
+        assert_eq!(scheduler.run(), SchedulerOutcome::Halted);
+        assert_eq!(scheduler.send_counts(), vec![2, 1, 1]);
+        assert_eq!(*scheduler.machines()[0].registers().get_reg('a'), 3);
+
+        // The last send goes to a machine that has already halted, so it just stays queued.
+        assert_eq!(scheduler.machines()[1].inbox().len(), 1);
+        assert_eq!(scheduler.machines()[1].outbox().len(), 0);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut machine = Machine::new(Program::load(
+r"set a 1
+add a 2
+snd a
+mul a 10
+rcv b"));
+
+        machine.push_input(7);
+        machine.step();
+        machine.step();
+        let snapshot = machine.snapshot();
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 30);
+
+        machine.restore(&snapshot);
+        assert_eq!(machine.ip(), 2);
+        assert_eq!(machine.steps(), 2);
+        assert_eq!(*machine.registers().get_reg('a'), 3);
+        assert_eq!(machine.inbox().iter().cloned().collect::<Vec<i64>>(), vec![7]);
+        assert!(machine.outbox().is_empty());
+        assert!(snapshot == machine.snapshot());
+    }
+
+    #[test]
+    fn step_back() {
+        let mut machine = Machine::new("cpy 2 a\ntgl a\ninc a\ninc a".parse::<Program<Assembunny>>().unwrap());
+        assert!(!machine.step_back());
+
+        machine.set_history_capacity(10);
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 2);
+        assert_eq!(machine.history_len(), 4);
+
+        // The tgl turned the last inc into dec. Undoing it puts the inc back.
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert_eq!(machine.ip(), 1);
+        assert_eq!(*machine.registers().get_reg('a'), 2);
+        assert_eq!(format!("{}", machine.program()), "cpy 2 a\ntgl a\ninc a\ninc a\n");
+
+        assert_eq!(machine.run(), MachineStatus::Halted);
+        assert_eq!(*machine.registers().get_reg('a'), 2);
+    }
+
+    #[test]
+    fn history_capacity() {
+        let mut machine = Machine::new(Program::load("add a 1\nadd a 1\nadd a 1\nadd a 1"));
+        machine.set_history_capacity(2);
+        machine.run();
+        assert_eq!(machine.history_len(), 2);
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert!(!machine.step_back());
+        assert_eq!(*machine.registers().get_reg('a'), 2);
+    }
+
+    #[test]
+    fn find_cycle() {
+        let mut machine = Machine::new(Program::load(
+r"set a 0
+add a 1
+mod a 3
+jgz 1 -2"));
+
+        // After the first step, a goes 0, 1, 1, 1, 2, 2, 2, 0, 0, 0, 1...
+        assert_eq!(machine.find_cycle(100), Some(StateCycle { start : 1, len : 9 }));
+
+        let mut machine = Machine::new(Program::load("add a 1\njgz 1 -1"));
+        assert_eq!(machine.find_cycle(100), None);
+    }
+
+    #[test]
+    fn snapshot_hashing() {
+        let program = "cpy 1 a\ntgl 1\ncpy 1 b".parse::<Program<Assembunny>>().unwrap();
+        let mut first = Machine::new(program.clone());
+        let mut second = Machine::new(program);
+        first.step();
+        second.step();
+
+        let mut seen = HashMap::new();
+        seen.insert(first.snapshot(), 1);
+        assert_eq!(seen.get(&second.snapshot()), Some(&1));
+
+        // Toggling changes the state, even though the registers and ip don't.
+        let mut toggled = first.clone();
+        toggled.step();
+        second.step();
+        assert!(toggled.snapshot() == second.snapshot());
+        assert!(toggled.snapshot() != first.snapshot());
+        assert_eq!(format!("{}", first.program()), "cpy 1 a\ntgl 1\ncpy 1 b\n");
+        assert_eq!(format!("{}", toggled.program()), "cpy 1 a\ntgl 1\njnz 1 b\n");
+    }
+}
@@ -0,0 +1,298 @@
+use super::*;
+use std::fmt;
+use std::str::FromStr;
+
+#[macro_use]
+mod isa;
+mod analysis;
+mod assembler;
+mod assembunny;
+mod compile;
+mod debugger;
+mod device;
+mod disasm;
+mod fault;
+mod machine;
+mod optimize;
+mod parse;
+mod poly;
+mod registers;
+mod serialize;
+mod symbolic;
+mod trace;
+pub use self::analysis::*;
+pub use self::assembler::*;
+pub use self::assembunny::*;
+pub use self::compile::*;
+pub use self::debugger::*;
+pub use self::device::*;
+pub use self::disasm::*;
+pub use self::fault::*;
+pub use self::isa::*;
+pub use self::machine::*;
+pub use self::optimize::*;
+pub use self::parse::*;
+pub use self::poly::*;
+pub use self::registers::*;
+pub use self::serialize::*;
+pub use self::symbolic::*;
+pub use self::trace::*;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum RegisterOrValue {
+    Reg(char),
+    Val(i64),
+}
+
+instruction_set! {
+    // The duet instruction set from 2017 days 18 and 23.
+    pub enum Instruction {
+        Snd = "snd" (rv : RegisterOrValue) => |r| Effect::Send(r.evaluate(rv)),
+        Set = "set" (reg : char, rv : RegisterOrValue) => |r| Effect::Write(*reg, r.evaluate(rv)),
+        Add = "add" (reg : char, rv : RegisterOrValue) => |r| Effect::write(*reg, r.add(*r.get_reg(*reg), r.evaluate(rv))),
+        Sub = "sub" (reg : char, rv : RegisterOrValue) => |r| Effect::write(*reg, r.sub(*r.get_reg(*reg), r.evaluate(rv))),
+        Mul = "mul" (reg : char, rv : RegisterOrValue) => |r| Effect::write(*reg, r.mul(*r.get_reg(*reg), r.evaluate(rv))),
+        Mod = "mod" (reg : char, rv : RegisterOrValue) => |r| Effect::write(*reg, r.rem(*r.get_reg(*reg), r.evaluate(rv))),
+        Rcv = "rcv" (reg : char) => |r| Effect::Receive(*reg),
+        Jgz = "jgz" (cond : RegisterOrValue, offset : RegisterOrValue) => |r| {
+            Effect::Jump(if r.evaluate(cond) > 0 { Some(r.evaluate(offset)) } else { None })
+        },
+        Jnz = "jnz" (cond : RegisterOrValue, offset : RegisterOrValue) => |r| {
+            Effect::Jump(if r.evaluate(cond) != 0 { Some(r.evaluate(offset)) } else { None })
+        },
+    }
+    trace = fmt_duet_step;
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Program<I = Instruction> {
+    pub instructions : Vec<I>,
+}
+
+lazy_static! {
+    static ref RE_REGISTER : regex::Regex = Regex::new(r"^([a-zA-Z])$").expect("failed to compile regex");
+    static ref RE_VALUE : regex::Regex = Regex::new(r"^(-?\d+)$").expect("failed to compile regex");
+}
+
+impl RegisterOrValue {
+    pub fn from(input : &str) -> RegisterOrValue {
+        input.parse().unwrap_or_else(|e| {
+            panic!("invalid register or value {}: {}", input, e);
+        })
+    }
+}
+
+impl FromStr for RegisterOrValue {
+    type Err = ParseError;
+
+    fn from_str(input : &str) -> Result<RegisterOrValue, ParseError> {
+        RegisterOrValue::parse_operand(input)
+    }
+}
+
+impl fmt::Display for RegisterOrValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegisterOrValue::Reg(a) => write!(f, "{}", a),
+            RegisterOrValue::Val(a) => write!(f, "{}", a),
+        }
+    }
+}
+
+impl Instruction {
+    pub fn from(input : &str) -> Instruction {
+        input.parse().unwrap_or_else(|e| {
+            panic!("invalid move {}: {}", input, e);
+        })
+    }
+}
+
+fn fmt_duet_step(step : &TraceStep<Instruction>, f : &mut fmt::Formatter) -> fmt::Result {
+    let ops = step.operands;
+    match *step.instruction {
+        Instruction::Snd(ref rv) => write!(f, "snd {} ({})", rv, ops[0]),
+        Instruction::Set(ref reg, _) => write!(f, "{} <= {}", reg, step.result),
+        Instruction::Add(ref reg, _) => write!(f, "add {} ({}) {} => {}", reg, ops[0], ops[1], step.result),
+        Instruction::Sub(ref reg, _) => write!(f, "sub {} ({}) {} => {}", reg, ops[0], ops[1], step.result),
+        Instruction::Mul(ref reg, _) => write!(f, "mul {} ({}) {} => {}", reg, ops[0], ops[1], step.result),
+        Instruction::Mod(ref reg, _) => write!(f, "mod {} ({}) {} => {}", reg, ops[0], ops[1], step.result),
+        Instruction::Rcv(ref reg) => write!(f, "rcv {} <= {}", reg, step.result),
+        Instruction::Jgz(ref cond, ref offset) => write!(f, "jgz {} ({}) {} ({}) => {}", cond, ops[0], offset, ops[1], step.result),
+        Instruction::Jnz(ref cond, ref offset) => write!(f, "jnz {} ({}) {} ({}) => {}", cond, ops[0], offset, ops[1], step.result),
+    }
+}
+
+impl Program {
+    pub fn load(input : &str) -> Program {
+        Program::parse(input).unwrap_or_else(|errors| {
+            panic!("invalid program:\n{}", errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("\n"));
+        })
+    }
+
+    pub fn parse(input : &str) -> Result<Program, Vec<ParseError>> {
+        input.parse()
+    }
+}
+
+impl<I> Program<I>
+where I : InstructionSet {
+    // Replaces the instruction at the index with its toggled form. Returns false if the index is
+    // outside the program, in which case nothing happens.
+    pub fn toggle(&mut self, index : usize) -> bool {
+        if index < self.instructions.len() {
+            I::toggle_in(&mut self.instructions, index);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<I> FromStr for Program<I>
+where I : InstructionSet {
+    type Err = Vec<ParseError>;
+
+    // Parses every line, collecting all of the errors rather than stopping at the first one.
+    fn from_str(input : &str) -> Result<Program<I>, Vec<ParseError>> {
+        let mut instructions = vec![];
+        let mut errors = vec![];
+        for (i, line) in input.lines().enumerate() {
+            match line.parse::<I>() {
+                Ok(instruction) => instructions.push(instruction),
+                Err(e) => errors.push(e.at_line(i + 1)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(Program {
+                instructions,
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl<I> fmt::Display for Program<I>
+where I : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ret = write!(f, "");
+        for inst in &self.instructions {
+            ret = writeln!(f, "{}", inst);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load_print() {
+        let input =
+r"snd z
+snd -10
+set a z
+set a -10
+add a z
+add a -10
+sub a z
+sub a -10
+mul a z
+mul a -10
+mod a z
+mod a -10
+rcv z
+jgz a z
+jgz -10 -10
+jgz a -10
+jgz -10 a";
+        assert_eq!(input, format!("{}", Program::load(input)).trim());
+    }
+
+    #[test]
+    fn store_load() {
+        let mut holder = RegisterHolder::default();
+        for i in b'a' .. b'z'+1 {
+            *holder.get_reg_mut(char::from(i)) = i64::from(i);
+        }
+
+        for i in b'a' .. b'z'+1 {
+            assert_eq!(*holder.get_reg(char::from(i)), i64::from(i));
+        }
+    }
+
+    #[test]
+    fn store_load_inst() {
+        let mut holder = RegisterHolder::default();
+
+        holder.apply_instruction(&Instruction::Set('a', RegisterOrValue::Val(i64::from(b'a'))));
+
+        for i in b'b' .. b'z'+1 {
+            holder.apply_instruction(&Instruction::Set(char::from(i), RegisterOrValue::Reg(char::from(i - 1))));
+            holder.apply_instruction(&Instruction::Add(char::from(i), RegisterOrValue::Val(1)));
+        }
+
+        for i in b'a' .. b'z'+1 {
+            assert_eq!(*holder.get_reg(char::from(i)), i64::from(i));
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        let holder = RegisterHolder::default();
+        assert_eq!(holder.get_next_ip(&Instruction::Set('a', RegisterOrValue::Val(1)), 0), 1);
+        assert_eq!(holder.get_next_ip(&Instruction::Jgz(RegisterOrValue::Val(1), RegisterOrValue::Val(-10)), 0), usize::MAX);
+        assert_eq!(holder.get_next_ip(&Instruction::Jnz(RegisterOrValue::Val(1), RegisterOrValue::Val(-10)), 0), usize::MAX);
+    }
+
+    #[test]
+    fn jgz() {
+        let mut holder = RegisterHolder::default();
+        *holder.get_reg_mut('a') = 1;
+        assert_eq!(holder.get_next_ip(&Instruction::Jgz(RegisterOrValue::Val(0), RegisterOrValue::Val(2)), 0), 1);
+        assert_eq!(holder.get_next_ip(&Instruction::Jgz(RegisterOrValue::Val(1), RegisterOrValue::Val(2)), 0), 2);
+        assert_eq!(holder.get_next_ip(&Instruction::Jgz(RegisterOrValue::Reg('a'), RegisterOrValue::Val(2)), 0), 2);
+        assert_eq!(holder.get_next_ip(&Instruction::Jgz(RegisterOrValue::Reg('b'), RegisterOrValue::Val(2)), 0), 1);
+    }
+
+    #[test]
+    fn jnz() {
+        let mut holder = RegisterHolder::default();
+        *holder.get_reg_mut('a') = 1;
+        assert_eq!(holder.get_next_ip(&Instruction::Jnz(RegisterOrValue::Val(0), RegisterOrValue::Val(2)), 0), 1);
+        assert_eq!(holder.get_next_ip(&Instruction::Jnz(RegisterOrValue::Val(1), RegisterOrValue::Val(2)), 0), 2);
+        assert_eq!(holder.get_next_ip(&Instruction::Jnz(RegisterOrValue::Reg('a'), RegisterOrValue::Val(2)), 0), 2);
+        assert_eq!(holder.get_next_ip(&Instruction::Jnz(RegisterOrValue::Reg('b'), RegisterOrValue::Val(2)), 0), 1);
+    }
+
+    #[test]
+    fn parse_register_or_value() {
+        assert_eq!("q".parse(), Ok(RegisterOrValue::Reg('q')));
+        assert_eq!("-12".parse(), Ok(RegisterOrValue::Val(-12)));
+        assert_eq!("qq".parse::<RegisterOrValue>(), Err(ParseError::new(1, "qq", &["<reg|value>"])));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("set 1 a".parse::<Instruction>(), Err(ParseError::new(5, "1", &["<reg>"])));
+        assert_eq!("add a".parse::<Instruction>(), Err(ParseError::new(6, "", &["<reg|value>"])));
+        assert_eq!("rcv a b".parse::<Instruction>(), Err(ParseError::new(7, "b", &["end of line"])));
+        assert_eq!("  foo a".parse::<Instruction>(), Err(ParseError::new(3, "foo", &Instruction::forms())));
+        assert_eq!("".parse::<Instruction>(), Err(ParseError::new(1, "", &Instruction::forms())));
+    }
+
+    #[test]
+    fn parse_program_collects_errors() {
+        let errors = Program::parse(
+r"set a 1
+jgz a b c
+snd 5
+mul 3 x").err().unwrap();
+
+        assert_eq!(errors, vec![
+            ParseError::new(9, "c", &["end of line"]).at_line(2),
+            ParseError::new(5, "3", &["<reg>"]).at_line(4),]);
+    }
+}
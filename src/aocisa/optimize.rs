@@ -0,0 +1,539 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+// What running a loop until it exits does, worked out ahead of time. Each update is the register's
+// final value in terms of the register values when the loop is entered. The summary only holds if
+// every guard is at least 1 on entry; otherwise the loop has to run the slow way. Each bound is at
+// least the magnitude of a value the loop works out along the way, in terms of the magnitudes of
+// the register values on entry, so that checked registers can tell ahead of time that the loop
+// won't overflow.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LoopSummary {
+    pub guards : Vec<Poly>,
+    pub updates : BTreeMap<char, Poly>,
+    pub bounds : Vec<Poly>,
+}
+
+// An instruction in an optimized program. Either it's from the original program, or it's the head
+// of a loop, fused into a single instruction that runs the whole loop at once and jumps past it.
+// The rest of the loop is left in place, so jumps into the middle of it still work, and the
+// fallback runs instead whenever the summary's guards don't hold.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Optimized<I> {
+    Plain(I),
+    Loop {
+        fallback : Box<Optimized<I>>,
+        summary : LoopSummary,
+        len : usize,
+    },
+}
+
+// Register values partway through a loop body, in terms of their values at the top of the body.
+#[derive(Default)]
+struct SymbolicState {
+    values : BTreeMap<char, Poly>,
+    guards : Vec<Poly>,
+    bounds : Vec<Poly>,
+}
+
+impl SymbolicState {
+    fn get(&self, reg : char) -> Poly {
+        self.values.get(&reg).cloned().unwrap_or_else(|| Poly::register(reg))
+    }
+
+    fn operand(&self, rv : &RegisterOrValue) -> Poly {
+        match *rv {
+            RegisterOrValue::Reg(r) => self.get(r),
+            RegisterOrValue::Val(v) => Poly::constant(v),
+        }
+    }
+
+    fn modified(&self) -> BTreeSet<char> {
+        self.values.iter().filter(|&(r, value)| *value != Poly::register(*r)).map(|(r, _)| *r).collect()
+    }
+
+    // Returns false if the op can't be represented, or a coefficient overflows, which ends the
+    // analysis of the loop.
+    fn apply_op(&mut self, op : &Op) -> bool {
+        let (reg, value) = match *op {
+            Op::Set(reg, ref rv) => (reg, Some(self.operand(rv))),
+            Op::Add(reg, ref rv) => (reg, self.get(reg).checked_add(self.operand(rv))),
+            Op::Sub(reg, ref rv) => (reg, self.get(reg).checked_sub(self.operand(rv))),
+            Op::Mul(reg, ref rv) => (reg, self.get(reg).checked_mul(self.operand(rv))),
+            Op::Nop => return true,
+            Op::Jump(ref cond, _) => return cond.constant() == Some(false),
+            _ => return false,
+        };
+
+        match value.and_then(|value| value.checked_abs().map(|bound| (value, bound))) {
+            Some((value, bound)) => {
+                self.values.insert(reg, value);
+                self.bounds.push(bound);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Returns false if a coefficient overflows.
+    fn apply_summary(&mut self, summary : &LoopSummary) -> bool {
+        let substitute = |p : &Poly| p.substitute(|r| self.get(r));
+        let guards : Option<Vec<Poly>> = summary.guards.iter().map(&substitute).collect();
+        let updates : Option<Vec<(char, Poly)>> = summary.updates.iter().map(|(r, p)| substitute(p).map(|p| (*r, p))).collect();
+
+        // The bounds are in terms of magnitudes, so they carry over using the magnitudes of the
+        // values here.
+        let magnitudes : Option<BTreeMap<char, Poly>> = summary.bounds.iter().flat_map(|b| b.registers()).map(|r| {
+            self.get(r).checked_abs().map(|m| (r, m))
+        }).collect();
+        let bounds : Option<Vec<Poly>> = magnitudes.and_then(|magnitudes| {
+            summary.bounds.iter().map(|b| b.substitute(|r| magnitudes[&r].clone())).collect()
+        });
+
+        match (guards, updates, bounds) {
+            (Some(guards), Some(updates), Some(bounds)) => {
+                self.guards.extend(guards);
+                self.values.extend(updates);
+                self.bounds.extend(bounds);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Turns the effect of one trip through a loop body into the effect of the whole loop, given
+    // the jump at the bottom. This works when the jump tests a counter that the body steps by one
+    // towards zero, and every other register either has a loop-invariant amount added to it each
+    // time or is set to a loop-invariant value.
+    fn close_loop(self, latch : &Op) -> Option<LoopSummary> {
+        let (counter, positive_only) = match *latch {
+            Op::Jump(Condition::NonZero(RegisterOrValue::Reg(c)), _) => (c, false),
+            Op::Jump(Condition::Positive(RegisterOrValue::Reg(c)), _) => (c, true),
+            _ => return None,
+        };
+
+        let trips = match self.get(counter).checked_sub(Poly::register(counter))?.as_constant() {
+            Some(-1) => Poly::register(counter),
+            Some(1) if !positive_only => Poly::register(counter).checked_neg()?,
+            _ => return None,
+        };
+
+        let modified = self.modified();
+        let is_invariant = |p : &Poly| p.registers().is_disjoint(&modified);
+        if !self.guards.iter().all(&is_invariant) {
+            return None;
+        }
+
+        // magnitudes bounds how big each register gets at the top of the body on any trip. The
+        // counter only moves towards zero.
+        let mut updates = BTreeMap::new();
+        let mut magnitudes = BTreeMap::new();
+        for reg in modified.iter().cloned().filter(|r| *r != counter) {
+            let value = self.get(reg);
+            let delta = value.clone().checked_sub(Poly::register(reg))?;
+            if is_invariant(&delta) {
+                let growth = delta.checked_abs()?.checked_mul(Poly::register(counter))?;
+                magnitudes.insert(reg, Poly::register(reg).checked_add(growth)?);
+                updates.insert(reg, Poly::register(reg).checked_add(delta.checked_mul(trips.clone())?)?);
+            } else if is_invariant(&value) {
+                magnitudes.insert(reg, Poly::register(reg).checked_add(value.checked_abs()?)?);
+                updates.insert(reg, value);
+            } else {
+                return None;
+            }
+        }
+
+        updates.insert(counter, Poly::zero());
+
+        let bounds : Option<Vec<Poly>> = self.bounds.iter().map(|b| {
+            b.substitute(|r| magnitudes.get(&r).cloned().unwrap_or_else(|| Poly::register(r)))
+        }).collect();
+
+        let mut guards = self.guards;
+        guards.push(trips);
+        Some(LoopSummary {
+            guards,
+            updates,
+            bounds : bounds?,
+        })
+    }
+}
+
+// Works out what the loop does as a whole, if it's simple enough. Loops inside it must already
+// have been fused.
+fn summarize<I>(instructions : &[Optimized<I>], lp : &Loop) -> Option<LoopSummary>
+where I : Analyze {
+    let mut state = SymbolicState::default();
+    let mut i = lp.head;
+    while i < lp.latch {
+        match instructions[i] {
+            Optimized::Loop { ref summary, len, .. } if i + len <= lp.latch => {
+                if !state.apply_summary(summary) {
+                    return None;
+                }
+
+                i += len;
+            },
+            ref instruction => {
+                if !state.apply_op(&instruction.original().op()) {
+                    return None;
+                }
+
+                i += 1;
+            },
+        }
+    }
+
+    state.close_loop(&instructions[lp.latch].original().op())
+}
+
+// Fuses every loop that has a closed form, innermost first, so that nested loops like the ones
+// that multiply by repeated addition collapse completely.
+pub fn optimize<I>(program : &Program<I>) -> Program<Optimized<I>>
+where I : InstructionSet<Register = char> + Analyze {
+    let mut instructions : Vec<Optimized<I>> = program.instructions.iter().cloned().map(Optimized::Plain).collect();
+    let mut loops = find_loops(program);
+    loops.sort_by_key(|lp| lp.len());
+
+    for lp in loops {
+        if let Some(summary) = summarize(&instructions, &lp) {
+            let fallback = Box::new(instructions[lp.head].clone());
+            instructions[lp.head] = Optimized::Loop {
+                fallback,
+                summary,
+                len : lp.len(),
+            };
+        }
+    }
+
+    Program {
+        instructions,
+    }
+}
+
+impl<I> Optimized<I> {
+    // The instruction from the original program at this position.
+    pub fn original(&self) -> &I {
+        match *self {
+            Optimized::Plain(ref instruction) => instruction,
+            Optimized::Loop { ref fallback, .. } => fallback.original(),
+        }
+    }
+
+    fn covers(&self, head : usize, index : usize) -> bool {
+        match *self {
+            Optimized::Plain(_) => false,
+            Optimized::Loop { len, .. } => head <= index && index < head + len,
+        }
+    }
+}
+
+impl<I> InstructionSet for Optimized<I>
+where I : InstructionSet<Register = char> {
+    type Register = char;
+
+    fn opcode(&self) -> &'static str {
+        self.original().opcode()
+    }
+
+    fn forms() -> Vec<String> {
+        I::forms()
+    }
+
+    fn operand_values(&self, registers : &RegisterHolder) -> OperandValues {
+        self.original().operand_values(registers)
+    }
+
+    fn execute(&self, registers : &RegisterHolder) -> Effect {
+        match *self {
+            Optimized::Plain(ref instruction) => instruction.execute(registers),
+            Optimized::Loop { ref fallback, ref summary, len } => {
+                match summary.fuse(registers) {
+                    Some(writes) => Effect::Fused(writes, len as i64),
+                    None => fallback.execute(registers),
+                }
+            },
+        }
+    }
+
+    fn fmt_step(step : &TraceStep<Self>, f : &mut fmt::Formatter) -> fmt::Result {
+        match *step.instruction {
+            Optimized::Plain(ref instruction) => {
+                I::fmt_step(&TraceStep {
+                    instruction,
+                    operands : step.operands,
+                    result : step.result,
+                }, f)
+            },
+            Optimized::Loop { ref summary, .. } => {
+                write!(f, "{} [{}] => {}", step.instruction, summary, step.result)
+            },
+        }
+    }
+
+    fn toggled(&self) -> Self {
+        Optimized::Plain(self.original().toggled())
+    }
+
+    // A fused loop no longer does what its summary says once any of its instructions change, so
+    // those go back to running normally.
+    fn toggle_in(instructions : &mut [Self], index : usize) {
+        instructions[index] = instructions[index].toggled();
+        for (head, instruction) in instructions.iter_mut().enumerate() {
+            while instruction.covers(head, index) {
+                let fallback = match *instruction {
+                    Optimized::Loop { ref fallback, .. } => (**fallback).clone(),
+                    Optimized::Plain(_) => unreachable!(),
+                };
+
+                *instruction = fallback;
+            }
+        }
+    }
+}
+
+impl<I> FromStr for Optimized<I>
+where I : FromStr {
+    type Err = I::Err;
+
+    fn from_str(input : &str) -> Result<Optimized<I>, I::Err> {
+        input.parse().map(Optimized::Plain)
+    }
+}
+
+impl<I> fmt::Display for Optimized<I>
+where I : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.original())
+    }
+}
+
+impl LoopSummary {
+    // The final register values, if running the summary is sure to do exactly what stepping
+    // through the loop would. Wrapping arithmetic can be done in any order and still come out the
+    // same, but checked registers need every value along the way to fit, or else stepping through
+    // the loop is what finds out where it faults. Saturating arithmetic depends on the order, so it
+    // always steps.
+    fn fuse(&self, registers : &RegisterHolder) -> Option<Vec<(char, i64)>> {
+        let wrap = match registers.overflow() {
+            Overflow::Wrap => true,
+            Overflow::Checked => {
+                let (_, max) = registers.range();
+                if !self.bounds.iter().all(|b| b.bound(registers).is_some_and(|b| b <= i128::from(max))) {
+                    return None;
+                }
+
+                false
+            },
+            Overflow::Saturate => return None,
+        };
+
+        let value = |p : &Poly| {
+            if wrap {
+                registers.fit(i128::from(p.evaluate_wrapping(registers))).ok()
+            } else {
+                p.evaluate(registers).and_then(|v| registers.fit(v).ok())
+            }
+        };
+
+        if !self.guards.iter().all(|g| value(g).is_some_and(|v| v >= 1)) {
+            return None;
+        }
+
+        self.updates.iter().map(|(r, p)| value(p).map(|v| (*r, v))).collect()
+    }
+}
+
+impl fmt::Display for LoopSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let updates : Vec<String> = self.updates.iter().map(|(r, p)| format!("{} = {}", r, p)).collect();
+        let guards : Vec<String> = self.guards.iter().map(|g| format!("{} >= 1", g)).collect();
+        write!(f, "{} if {}", updates.join(", "), guards.join(" and "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Runs the program as is and optimized, checking that both end up with the same registers.
+    // Returns how many steps each took.
+    fn compare<I>(program : Program<I>) -> (u64, u64)
+    where I : InstructionSet<Register = char> + Analyze {
+        let optimized = optimize(&program);
+
+        let mut machine = Machine::new(program);
+        assert_eq!(machine.run(), MachineStatus::Halted);
+
+        let mut fast_machine = Machine::new(optimized);
+        assert_eq!(fast_machine.run(), MachineStatus::Halted);
+
+        assert_eq!(machine.registers(), fast_machine.registers());
+        (machine.steps(), fast_machine.steps())
+    }
+
+    #[test]
+    fn add_loop() {
+        let program = Program::load(
+r"set a 3
+set b 10
+set c 4
+add a b
+add e 2
+sub c 1
+jgz c -3");
+
+        let optimized = optimize(&program);
+        assert_eq!(format!("{}", optimized), format!("{}", program));
+        match optimized.instructions[3] {
+            Optimized::Loop { ref summary, len, .. } => {
+                assert_eq!(len, 4);
+                assert_eq!(format!("{}", summary), "a = b*c + a, c = 0, e = 2*c + e if c >= 1");
+            },
+            ref other => panic!("loop wasn't fused: {:?}", other),
+        }
+
+        assert_eq!(compare(program), (19, 4));
+    }
+
+    #[test]
+    fn overflow_modes() {
+        // Each mode and width has to stop in the same place with the same registers, fused or not.
+        let programs = [
+            "set b 40\nadd a 100\nsub a 99\nsub b 1\njnz b -3",
+            "set b 3\nadd a 120\nadd a 10\nsub a 130\nsub b 1\njnz b -4\nadd a 1",
+            "set a 3\nset b 10\nset c 4\nadd a b\nadd e 2\nsub c 1\njgz c -3",
+            "set b 30\nset d 3\nset c b\nadd a 1\nsub c 1\njnz c -2\nsub d 1\njnz d -5",
+            "set b 4611686018427387904\nset c 4\nadd a b\nsub c 1\njnz c -2",
+        ];
+
+        for program in &programs {
+            let program = Program::load(program);
+            for &bits in &[8, 64] {
+                for &overflow in &[Overflow::Checked, Overflow::Wrap, Overflow::Saturate] {
+                    let mut machine = Machine::new(program.clone());
+                    *machine.registers_mut() = RegisterHolder::with_word(bits, overflow);
+                    let outcome = machine.execute(10000);
+
+                    let mut fast_machine = Machine::new(optimize(&program));
+                    *fast_machine.registers_mut() = RegisterHolder::with_word(bits, overflow);
+                    assert_eq!(fast_machine.execute(10000), outcome, "{} bits, {:?}:\n{}", bits, overflow, program);
+                    assert_eq!(fast_machine.ip(), machine.ip());
+                    assert_eq!(fast_machine.registers(), machine.registers());
+                }
+            }
+        }
+
+        // Only the modes that are sure to match get the fast path.
+        let program = Program::load(programs[3]);
+        let steps = |bits, overflow| {
+            let mut machine = Machine::new(optimize(&program));
+            *machine.registers_mut() = RegisterHolder::with_word(bits, overflow);
+            assert_eq!(machine.execute(10000), Outcome::Halted);
+            machine.steps()
+        };
+
+        assert_eq!(steps(64, Overflow::Checked), 3);
+        assert_eq!(steps(64, Overflow::Wrap), 3);
+        assert_eq!(steps(8, Overflow::Wrap), 3);
+        assert_eq!(steps(8, Overflow::Checked), 3);
+        assert!(steps(64, Overflow::Saturate) > 90);
+    }
+
+    #[test]
+    fn coefficient_overflow() {
+        // The summary would need a coefficient too big for an i64, so the loop isn't fused.
+        let program = Program::load("set c 2\nadd a 9223372036854775807\nadd a 9223372036854775807\nsub c 1\njgz c -3");
+        let plain : Vec<Optimized<Instruction>> = program.instructions.iter().cloned().map(Optimized::Plain).collect();
+        assert_eq!(optimize(&program).instructions, plain);
+    }
+
+    #[test]
+    fn guard_fails() {
+        // c starts at 0, so jgz leaves the loop after one trip instead of running it c times.
+        assert_eq!(compare(Program::load(
+r"set a 3
+set b 10
+add a b
+sub c 1
+jgz c -2")), (5, 5));
+    }
+
+    #[test]
+    fn nested_multiply() {
+        let program : Program<Assembunny> =
+r"cpy 7 a
+cpy 5 b
+cpy 3 d
+cpy b c
+inc a
+dec c
+jnz c -2
+dec d
+jnz d -5".parse().unwrap();
+
+        let optimized = optimize(&program);
+        match optimized.instructions[3] {
+            Optimized::Loop { ref summary, .. } => {
+                assert_eq!(format!("{}", summary), "a = b*d + a, c = 0, d = 0 if b >= 1 and d >= 1");
+            },
+            ref other => panic!("loop wasn't fused: {:?}", other),
+        }
+
+        assert_eq!(compare(program), (3 + 3 * (1 + 5 * 3 + 2), 4));
+    }
+
+    #[test]
+    fn fibonacci() {
+        // The example from 2016 day 12, plus a longer run of the same loop.
+        compare(r"cpy 41 a
+inc a
+inc a
+dec a
+jnz a 2
+dec a".parse::<Program<Assembunny>>().unwrap());
+
+        let (slow, fast) = compare(r"cpy 1 a
+cpy 1 b
+cpy 26 d
+cpy 7 c
+inc d
+dec c
+jnz c -2
+cpy a c
+inc a
+dec b
+jnz b -2
+cpy c b
+dec d
+jnz d -6
+cpy 13 c
+cpy 14 d
+inc a
+dec d
+jnz d -2
+dec c
+jnz c -5".parse::<Program<Assembunny>>().unwrap());
+        assert!(fast < slow / 10);
+    }
+
+    #[test]
+    fn toggle_undoes_fusion() {
+        // The tgl turns inc b into dec b, so the fused loop is no longer right.
+        let program : Program<Assembunny> =
+r"cpy 3 c
+tgl 2
+inc a
+inc b
+dec c
+jnz c -3".parse().unwrap();
+
+        compare(program.clone());
+
+        let mut optimized = optimize(&program);
+        assert!(optimized.toggle(3));
+        assert_eq!(optimized.instructions[2], Optimized::Plain("inc a".parse().unwrap()));
+        assert_eq!(optimized.instructions[3], Optimized::Plain("dec b".parse().unwrap()));
+    }
+}
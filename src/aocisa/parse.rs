@@ -0,0 +1,161 @@
+use super::*;
+use std::error::Error;
+
+const END_OF_LINE : &str = "end of line";
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseError {
+    // Both 1-based. The column counts characters, not bytes.
+    pub line : usize,
+    pub column : usize,
+
+    // Empty if the line ended before the expected token.
+    pub token : String,
+
+    // The forms that would have been accepted at this position.
+    pub expected : Vec<String>,
+}
+
+// A whitespace-separated piece of a line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Token<'t> {
+    pub column : usize,
+    pub text : &'t str,
+}
+
+// Hands out the tokens of one line in order, producing errors that point at the right column.
+pub struct TokenStream<'t> {
+    tokens : Vec<Token<'t>>,
+    end_column : usize,
+    pos : usize,
+}
+
+impl ParseError {
+    pub fn new<S>(column : usize, token : &str, expected : &[S]) -> ParseError
+    where S : AsRef<str> {
+        ParseError {
+            line : 1,
+            column,
+            token : token.to_string(),
+            expected : expected.iter().map(|e| e.as_ref().to_string()).collect(),
+        }
+    }
+
+    pub fn at_line(mut self, line : usize) -> ParseError {
+        self.line = line;
+        self
+    }
+
+    pub fn expecting<S>(mut self, expected : &[S]) -> ParseError
+    where S : AsRef<str> {
+        self.expected = expected.iter().map(|e| e.as_ref().to_string()).collect();
+        self
+    }
+
+    fn offset_by(mut self, token : &Token) -> ParseError {
+        self.column += token.column - 1;
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: found ", self.line, self.column)?;
+        if self.token.is_empty() {
+            write!(f, "{}", END_OF_LINE)?;
+        } else {
+            write!(f, "`{}`", self.token)?;
+        }
+
+        if self.expected.len() == 1 {
+            write!(f, ", expected {}", self.expected[0])
+        } else {
+            write!(f, ", expected one of: {}", self.expected.join(", "))
+        }
+    }
+}
+
+impl Error for ParseError {
+}
+
+pub fn tokenize(input : &str) -> Vec<Token<'_>> {
+    lazy_static! {
+        static ref RE_TOKEN : regex::Regex = Regex::new(r"\S+").expect("failed to compile regex");
+    }
+
+    RE_TOKEN.find_iter(input).map(|m| {
+        Token {
+            column : input[.. m.start()].chars().count() + 1,
+            text : m.as_str(),
+        }
+    }).collect()
+}
+
+impl<'t> TokenStream<'t> {
+    pub fn new(input : &'t str) -> TokenStream<'t> {
+        TokenStream {
+            tokens : tokenize(input),
+            end_column : input.chars().count() + 1,
+            pos : 0,
+        }
+    }
+
+    pub fn next_token<S>(&mut self, expected : &[S]) -> Result<Token<'t>, ParseError>
+    where S : AsRef<str> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(*token)
+            },
+            None => Err(ParseError::new(self.end_column, "", expected)),
+        }
+    }
+
+    // Parses the next token with a parser that reports columns relative to the start of the token.
+    pub fn parse_next<T, F>(&mut self, expected : &str, parser : F) -> Result<T, ParseError>
+    where F : Fn(&str) -> Result<T, ParseError> {
+        let token = self.next_token(&[expected])?;
+        parser(token.text).map_err(|e| e.offset_by(&token))
+    }
+
+    pub fn finish(&self) -> Result<(), ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(ParseError::new(token.column, token.text, &[END_OF_LINE])),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(tokenize("  ab c\td "), vec![
+            Token { column : 3, text : "ab" },
+            Token { column : 6, text : "c" },
+            Token { column : 8, text : "d" },]);
+    }
+
+    #[test]
+    fn display_one() {
+        let error = ParseError::new(5, "1x", &["<reg>"]).at_line(3);
+        assert_eq!(format!("{}", error), "line 3, column 5: found `1x`, expected <reg>");
+    }
+
+    #[test]
+    fn display_many() {
+        let error = ParseError::new(7, "", &["<reg>", "<value>"]);
+        assert_eq!(format!("{}", error), "line 1, column 7: found end of line, expected one of: <reg>, <value>");
+    }
+
+    #[test]
+    fn stream() {
+        let mut stream = TokenStream::new("set  q");
+        assert_eq!(stream.next_token(&["op"]).unwrap().text, "set");
+        assert_eq!(stream.parse_next("<reg>", |t| Err::<(), _>(ParseError::new(1, t, &["<reg>"]))), Err(ParseError::new(6, "q", &["<reg>"])));
+        assert_eq!(stream.next_token(&["<value>"]), Err(ParseError::new(7, "", &["<value>"])));
+        assert_eq!(stream.finish(), Ok(()));
+    }
+}
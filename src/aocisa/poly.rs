@@ -0,0 +1,232 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+// A polynomial over register values with integer coefficients, like 3*a*b + c - 4. Each term is
+// keyed by its registers in sorted order, with repeats for powers; the constant term has no
+// registers.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Poly {
+    terms : BTreeMap<Vec<char>, i64>,
+}
+
+impl Poly {
+    pub fn zero() -> Poly {
+        Poly::default()
+    }
+
+    pub fn constant(value : i64) -> Poly {
+        let mut poly = Poly::zero();
+        if value != 0 {
+            poly.terms.insert(vec![], value);
+        }
+        poly
+    }
+
+    pub fn register(reg : char) -> Poly {
+        let mut poly = Poly::zero();
+        poly.terms.insert(vec![reg], 1);
+        poly
+    }
+
+    pub fn from_operand(rv : &RegisterOrValue) -> Poly {
+        match *rv {
+            RegisterOrValue::Reg(r) => Poly::register(r),
+            RegisterOrValue::Val(v) => Poly::constant(v),
+        }
+    }
+
+    // Returns None if the coefficient overflows.
+    fn add_term(&mut self, registers : Vec<char>, coefficient : i64) -> Option<()> {
+        let sum = self.terms.get(&registers).cloned().unwrap_or(0).checked_add(coefficient)?;
+        if sum == 0 {
+            self.terms.remove(&registers);
+        } else {
+            self.terms.insert(registers, sum);
+        }
+        Some(())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&vec![]).cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn registers(&self) -> BTreeSet<char> {
+        self.terms.keys().flat_map(|regs| regs.iter().cloned()).collect()
+    }
+
+    pub fn terms(&self) -> std::collections::btree_map::Iter<'_, Vec<char>, i64> {
+        self.terms.iter()
+    }
+
+    fn evaluate_with<F>(&self, coefficient_of : fn(i64) -> i128, value_of : F) -> Option<i128>
+    where F : Fn(char) -> i128 {
+        self.terms.iter().try_fold(0i128, |sum, (regs, coefficient)| {
+            let term = regs.iter().try_fold(coefficient_of(*coefficient), |product, r| product.checked_mul(value_of(*r)))?;
+            sum.checked_add(term)
+        })
+    }
+
+    // The exact value, or None if even an i128 can't hold it.
+    pub fn evaluate(&self, registers : &RegisterHolder) -> Option<i128> {
+        self.evaluate_with(i128::from, |r| i128::from(*registers.get_reg(r)))
+    }
+
+    // The value with 64-bit wrapping arithmetic throughout.
+    pub fn evaluate_wrapping(&self, registers : &RegisterHolder) -> i64 {
+        self.terms.iter().fold(0i64, |sum, (regs, coefficient)| {
+            sum.wrapping_add(regs.iter().fold(*coefficient, |product, r| product.wrapping_mul(*registers.get_reg(*r))))
+        })
+    }
+
+    // The most the magnitude can be when every register's magnitude is at most its value in
+    // registers, or None if even an i128 can't hold it.
+    pub fn bound(&self, registers : &RegisterHolder) -> Option<i128> {
+        self.evaluate_with(|c| i128::from(c.unsigned_abs()), |r| i128::from(registers.get_reg(r).unsigned_abs()))
+    }
+
+    // The same terms with every coefficient made positive.
+    pub fn checked_abs(&self) -> Option<Poly> {
+        let mut result = self.clone();
+        for coefficient in result.terms.values_mut() {
+            *coefficient = coefficient.checked_abs()?;
+        }
+        Some(result)
+    }
+
+    // Replaces every register with a polynomial. None if a coefficient overflows.
+    pub fn substitute<F>(&self, value_of : F) -> Option<Poly>
+    where F : Fn(char) -> Poly {
+        let mut result = Poly::zero();
+        for (regs, coefficient) in &self.terms {
+            let term = regs.iter().try_fold(Poly::constant(*coefficient), |product, r| product.checked_mul(value_of(*r)))?;
+            result = result.checked_add(term)?;
+        }
+        Some(result)
+    }
+
+    // Arithmetic returns None if any coefficient overflows, like the integer checked_ methods.
+    pub fn checked_add(mut self, other : Poly) -> Option<Poly> {
+        for (regs, coefficient) in other.terms {
+            self.add_term(regs, coefficient)?;
+        }
+        Some(self)
+    }
+
+    pub fn checked_neg(mut self) -> Option<Poly> {
+        for coefficient in self.terms.values_mut() {
+            *coefficient = coefficient.checked_neg()?;
+        }
+        Some(self)
+    }
+
+    pub fn checked_sub(self, other : Poly) -> Option<Poly> {
+        self.checked_add(other.checked_neg()?)
+    }
+
+    pub fn checked_mul(self, other : Poly) -> Option<Poly> {
+        let mut result = Poly::zero();
+        for (regs_a, coefficient_a) in &self.terms {
+            for (regs_b, coefficient_b) in &other.terms {
+                let mut regs = regs_a.clone();
+                regs.extend_from_slice(regs_b);
+                regs.sort();
+                result.add_term(regs, coefficient_a.checked_mul(*coefficient_b)?)?;
+            }
+        }
+        Some(result)
+    }
+}
+
+impl fmt::Display for Poly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+
+        // Highest degree first, with the constant at the end.
+        let mut terms : Vec<(&Vec<char>, &i64)> = self.terms.iter().collect();
+        terms.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(b.0)));
+
+        for (i, &(regs, coefficient)) in terms.iter().enumerate() {
+            let magnitude = coefficient.unsigned_abs();
+            if i == 0 {
+                if *coefficient < 0 {
+                    write!(f, "-")?;
+                }
+            } else {
+                write!(f, " {} ", if *coefficient < 0 { '-' } else { '+' })?;
+            }
+
+            if regs.is_empty() {
+                write!(f, "{}", magnitude)?;
+            } else {
+                if magnitude != 1 {
+                    write!(f, "{}*", magnitude)?;
+                }
+
+                let names : Vec<String> = regs.iter().map(|r| r.to_string()).collect();
+                write!(f, "{}", names.join("*"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = Poly::register('a');
+        let b = Poly::register('b');
+        let p = a.clone().checked_add(Poly::constant(2)).unwrap().checked_mul(b.clone().checked_sub(a.clone()).unwrap()).unwrap();
+        assert_eq!(format!("{}", p), "-a*a + a*b - 2*a + 2*b");
+        assert_eq!(format!("{}", p.clone().checked_sub(p.clone()).unwrap()), "0");
+        assert_eq!(p.clone().checked_sub(p).unwrap().as_constant(), Some(0));
+        assert_eq!(Poly::constant(-3).as_constant(), Some(-3));
+        assert_eq!(a.as_constant(), None);
+    }
+
+    #[test]
+    fn overflow() {
+        let max = Poly::constant(i64::MAX);
+        assert_eq!(max.clone().checked_add(Poly::constant(1)), None);
+        assert_eq!(max.clone().checked_mul(Poly::constant(2)), None);
+        assert_eq!(Poly::constant(i64::MIN).checked_neg(), None);
+        assert_eq!(Poly::constant(i64::MIN).checked_abs(), None);
+        assert_eq!(max.checked_add(Poly::constant(-1)), Some(Poly::constant(i64::MAX - 1)));
+        assert_eq!(Poly::register('a').substitute(|_| Poly::constant(i64::MIN)), Some(Poly::constant(i64::MIN)));
+        assert_eq!(Poly::constant(-2).checked_mul(Poly::register('a')).unwrap().substitute(|_| Poly::constant(i64::MIN)), None);
+        assert_eq!(format!("{}", Poly::constant(i64::MIN)), "-9223372036854775808");
+    }
+
+    #[test]
+    fn evaluate_substitute() {
+        let mut registers = RegisterHolder::default();
+        *registers.get_reg_mut('a') = 3;
+        *registers.get_reg_mut('b') = 5;
+
+        let p = Poly::register('a').checked_mul(Poly::register('b')).unwrap().checked_add(Poly::constant(-1)).unwrap();
+        assert_eq!(p.evaluate(&registers), Some(14));
+        assert_eq!(p.bound(&registers), Some(16));
+
+        *registers.get_reg_mut('a') = i64::MAX;
+        assert_eq!(p.evaluate(&registers), Some(i128::from(i64::MAX) * 5 - 1));
+        assert_eq!(p.evaluate_wrapping(&registers), i64::MAX.wrapping_mul(5) - 1);
+
+        let q = p.substitute(|r| if r == 'a' { Poly::register('b').checked_add(Poly::constant(1)).unwrap() } else { Poly::register(r) }).unwrap();
+        assert_eq!(format!("{}", q), "b*b + b - 1");
+        assert_eq!(q.registers().into_iter().collect::<Vec<char>>(), vec!['b']);
+    }
+}
@@ -0,0 +1,432 @@
+use super::*;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+// How many registers live in a fixed array instead of a map. Enough for every letter in either
+// case, or the first 52 numbered registers.
+pub const FIXED_REGISTERS : usize = 52;
+
+static ZERO : i64 = 0;
+
+// Anything that can name a register. Registers with a slot are stored in a fixed array, which is
+// much faster than looking them up in the map where every other register goes.
+pub trait RegisterName : Copy + Ord + Hash + Debug {
+    fn slot(&self) -> Option<usize>;
+
+    // The register stored in a slot. Only called with slots that slot() returned.
+    fn from_slot(slot : usize) -> Self;
+}
+
+// What happens when a result doesn't fit in a register's word width.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Overflow {
+    // Report the overflow as a fault, which stops the machine without changing the register.
+    Checked,
+
+    Wrap,
+    Saturate,
+}
+
+// A register with a multi-character name, handed out by an Interner.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Interned(usize);
+
+// Gives each distinct name its own Interned register. The first names interned get fixed slots.
+#[derive(Default, Clone, Debug)]
+pub struct Interner {
+    names : Vec<String>,
+    ids : HashMap<String, Interned>,
+}
+
+// A register file. Registers are all 0 to start with. By default registers are 64 bits wide and
+// overflowing one is a Fault, but narrower words that wrap or saturate are available for puzzles
+// that simulate hardware.
+#[derive(Clone, Debug)]
+pub struct RegisterHolder<R = char> {
+    fixed : [i64 ; FIXED_REGISTERS],
+    others : BTreeMap<R, i64>,
+    bits : u32,
+    overflow : Overflow,
+}
+
+impl RegisterName for char {
+    fn slot(&self) -> Option<usize> {
+        match *self {
+            'a' ..= 'z' => Some(*self as usize - 'a' as usize),
+            'A' ..= 'Z' => Some(26 + *self as usize - 'A' as usize),
+            _ => None,
+        }
+    }
+
+    fn from_slot(slot : usize) -> char {
+        if slot < 26 {
+            (b'a' + slot as u8) as char
+        } else {
+            (b'A' + (slot - 26) as u8) as char
+        }
+    }
+}
+
+impl RegisterName for usize {
+    fn slot(&self) -> Option<usize> {
+        if *self < FIXED_REGISTERS {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+
+    fn from_slot(slot : usize) -> usize {
+        slot
+    }
+}
+
+impl RegisterName for Interned {
+    fn slot(&self) -> Option<usize> {
+        self.0.slot()
+    }
+
+    fn from_slot(slot : usize) -> Interned {
+        Interned(slot)
+    }
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, name : &str) -> Interned {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+
+        let id = Interned(self.names.len());
+        self.names.push(String::from(name));
+        self.ids.insert(String::from(name), id);
+        id
+    }
+
+    // The register for a name, if it has been interned.
+    pub fn get(&self, name : &str) -> Option<Interned> {
+        self.ids.get(name).cloned()
+    }
+
+    pub fn name(&self, reg : Interned) -> &str {
+        &self.names[reg.0]
+    }
+}
+
+impl<R> Default for RegisterHolder<R>
+where R : RegisterName {
+    fn default() -> RegisterHolder<R> {
+        RegisterHolder::with_word(64, Overflow::Checked)
+    }
+}
+
+// Registers that were never written are equal to ones that were set back to 0.
+impl<R> PartialEq for RegisterHolder<R>
+where R : RegisterName {
+    fn eq(&self, other : &RegisterHolder<R>) -> bool {
+        self.fixed[..] == other.fixed[..] &&
+            self.bits == other.bits &&
+            self.overflow == other.overflow &&
+            self.others.iter().filter(|&(_, v)| *v != 0).eq(other.others.iter().filter(|&(_, v)| *v != 0))
+    }
+}
+
+impl<R> Eq for RegisterHolder<R>
+where R : RegisterName {
+}
+
+impl<R> Hash for RegisterHolder<R>
+where R : RegisterName {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.fixed[..].hash(state);
+        self.bits.hash(state);
+        self.overflow.hash(state);
+        for entry in self.others.iter().filter(|&(_, v)| *v != 0) {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<R> RegisterHolder<R>
+where R : RegisterName {
+    pub fn new() -> RegisterHolder<R> {
+        RegisterHolder::default()
+    }
+
+    // Registers that are the given number of bits wide, from 1 to 64, holding signed values.
+    pub fn with_word(bits : u32, overflow : Overflow) -> RegisterHolder<R> {
+        if bits == 0 || bits > 64 {
+            panic!("registers can't be {} bits wide", bits);
+        }
+
+        RegisterHolder {
+            fixed : [0 ; FIXED_REGISTERS],
+            others : BTreeMap::new(),
+            bits,
+            overflow,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn get_reg_mut(&mut self, reg : R) -> &mut i64 {
+        match reg.slot() {
+            Some(slot) => &mut self.fixed[slot],
+            None => self.others.entry(reg).or_insert(0),
+        }
+    }
+
+    pub fn get_reg(&self, reg : R) -> &i64 {
+        match reg.slot() {
+            Some(slot) => &self.fixed[slot],
+            None => self.others.get(&reg).unwrap_or(&ZERO),
+        }
+    }
+
+    // Stores a value, making it fit in the word width first. Leaves the register alone if it doesn't
+    // fit in checked registers.
+    pub fn set_reg(&mut self, reg : R, value : i64) -> Result<(), Fault> {
+        *self.get_reg_mut(reg) = self.fit(i128::from(value))?;
+        Ok(())
+    }
+
+    // Every register that isn't 0, in order.
+    pub fn values(&self) -> Vec<(R, i64)> {
+        let mut values : Vec<(R, i64)> = self.fixed.iter().enumerate().filter(|&(_, v)| *v != 0).map(|(slot, v)| (R::from_slot(slot), *v)).collect();
+        values.extend(self.others.iter().filter(|&(_, v)| *v != 0).map(|(r, v)| (*r, *v)));
+        values.sort();
+        values
+    }
+
+    // The smallest and largest values a register can hold.
+    pub fn range(&self) -> (i64, i64) {
+        let max = ((1u64 << (self.bits - 1)) - 1) as i64;
+        (-max - 1, max)
+    }
+
+    // Makes an exact result fit in a register, according to the overflow behaviour. Only checked
+    // registers return an error.
+    pub fn fit(&self, value : i128) -> Result<i64, Fault> {
+        let (min, max) = self.range();
+        if i128::from(min) <= value && value <= i128::from(max) {
+            return Ok(value as i64);
+        }
+
+        match self.overflow {
+            Overflow::Checked => Err(Fault::Overflow),
+            Overflow::Saturate => Ok(if value < 0 { min } else { max }),
+            Overflow::Wrap => {
+                let shift = 128 - self.bits;
+                Ok(((value << shift) >> shift) as i64)
+            },
+        }
+    }
+
+    // Arithmetic for instruction sets to use, so that results respect the word width. Pass the
+    // results to Effect::write.
+    pub fn add(&self, a : i64, b : i64) -> Result<i64, Fault> {
+        self.fit(i128::from(a) + i128::from(b))
+    }
+
+    pub fn sub(&self, a : i64, b : i64) -> Result<i64, Fault> {
+        self.fit(i128::from(a) - i128::from(b))
+    }
+
+    pub fn mul(&self, a : i64, b : i64) -> Result<i64, Fault> {
+        self.fit(i128::from(a) * i128::from(b))
+    }
+
+    // The remainder, with the sign of a, like Rust's %.
+    pub fn rem(&self, a : i64, b : i64) -> Result<i64, Fault> {
+        if b == 0 {
+            Err(Fault::DivideByZero)
+        } else {
+            self.fit(i128::from(a) % i128::from(b))
+        }
+    }
+
+    pub fn apply_instruction<I>(&mut self, instruction : &I) -> bool
+    where I : InstructionSet<Register = R> {
+        self.apply_instruction_traced(instruction, &mut NullTracer)
+    }
+
+    // Applies the instruction if all it does is write a register that the value fits in. Returns
+    // whether it did.
+    pub fn apply_instruction_traced<I, T>(&mut self, instruction : &I, tracer : &mut T) -> bool
+    where I : InstructionSet<Register = R>,
+          T : Tracer<I> + ?Sized {
+        let operands = instruction.operand_values(self);
+        match instruction.execute(self) {
+            Effect::Write(reg, value) => {
+                if self.set_reg(reg, value).is_err() {
+                    return false;
+                }
+
+                tracer.trace(&TraceStep {
+                    instruction,
+                    operands : operands.as_slice(),
+                    result : TraceResult::Value(value),
+                });
+
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn get_next_ip<I>(&self, instruction : &I, current_ip : usize) -> usize
+    where I : InstructionSet<Register = R> {
+        self.get_next_ip_traced(instruction, current_ip, &mut NullTracer)
+    }
+
+    pub fn get_next_ip_traced<I, T>(&self, instruction : &I, current_ip : usize, tracer : &mut T) -> usize
+    where I : InstructionSet<Register = R>,
+          T : Tracer<I> + ?Sized {
+        let offset = match instruction.execute(self) {
+            Effect::Jump(target) => {
+                tracer.trace(&TraceStep {
+                    instruction,
+                    operands : instruction.operand_values(self).as_slice(),
+                    result : TraceResult::Branch(target.is_some()),
+                });
+
+                target.unwrap_or(1)
+            },
+            _ => 1,
+        };
+
+        offset_ip(current_ip, offset)
+    }
+}
+
+impl RegisterHolder {
+    pub fn evaluate(&self, rv : &RegisterOrValue) -> i64 {
+        match *rv {
+            RegisterOrValue::Reg(r) => {
+                *self.get_reg(r)
+            },
+            RegisterOrValue::Val(v) => {
+                v
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn letters() {
+        let mut holder = RegisterHolder::new();
+        *holder.get_reg_mut('A') = 1;
+        *holder.get_reg_mut('z') = 2;
+        *holder.get_reg_mut('#') = 3;
+        assert_eq!(*holder.get_reg('A'), 1);
+        assert_eq!(*holder.get_reg('a'), 0);
+        assert_eq!(*holder.get_reg('#'), 3);
+        assert_eq!(*holder.get_reg('$'), 0);
+        assert_eq!(holder.values(), vec![('#', 3), ('A', 1), ('z', 2)]);
+    }
+
+    #[test]
+    fn numbered() {
+        let mut holder : RegisterHolder<usize> = RegisterHolder::new();
+        for r in 0 .. 6 {
+            holder.set_reg(r, r as i64 * 10).unwrap();
+        }
+
+        holder.set_reg(1000, -1).unwrap();
+        assert_eq!(*holder.get_reg(5), 50);
+        assert_eq!(*holder.get_reg(1000), -1);
+        assert_eq!(holder.values(), vec![(1, 10), (2, 20), (3, 30), (4, 40), (5, 50), (1000, -1)]);
+    }
+
+    #[test]
+    fn interned() {
+        let mut names = Interner::new();
+        let acc = names.intern("acc");
+        let pc = names.intern("pc");
+        assert_eq!(names.intern("acc"), acc);
+        assert_eq!(names.get("pc"), Some(pc));
+        assert_eq!(names.get("sp"), None);
+        assert_eq!(names.name(pc), "pc");
+
+        let mut holder = RegisterHolder::new();
+        holder.set_reg(acc, 7).unwrap();
+        assert_eq!(*holder.get_reg(acc), 7);
+        assert_eq!(*holder.get_reg(pc), 0);
+    }
+
+    #[test]
+    fn unwritten_equals_zero() {
+        let mut a : RegisterHolder = RegisterHolder::new();
+        let b = RegisterHolder::new();
+        *a.get_reg_mut('#') = 5;
+        assert!(a != b);
+        *a.get_reg_mut('#') = 0;
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn word_width() {
+        let wrapping : RegisterHolder = RegisterHolder::with_word(8, Overflow::Wrap);
+        assert_eq!(wrapping.range(), (-128, 127));
+        assert_eq!(wrapping.add(127, 1), Ok(-128));
+        assert_eq!(wrapping.sub(-128, 1), Ok(127));
+        assert_eq!(wrapping.mul(16, 16), Ok(0));
+
+        let saturating : RegisterHolder = RegisterHolder::with_word(16, Overflow::Saturate);
+        assert_eq!(saturating.add(32000, 1000), Ok(32767));
+        assert_eq!(saturating.mul(-200, 200), Ok(-32768));
+
+        let mut wide : RegisterHolder = RegisterHolder::with_word(64, Overflow::Wrap);
+        assert_eq!(wide.add(i64::MAX, 1), Ok(i64::MIN));
+        assert_eq!(wide.rem(i64::MIN, -1), Ok(0));
+        wide.set_reg('a', 5).unwrap();
+        assert_eq!(*wide.get_reg('a'), 5);
+    }
+
+    #[test]
+    fn overflow_faults_by_default() {
+        let mut holder : RegisterHolder = RegisterHolder::new();
+        assert_eq!(holder.mul(i64::MAX, 2), Err(Fault::Overflow));
+
+        let mut narrow : RegisterHolder = RegisterHolder::with_word(8, Overflow::Checked);
+        assert_eq!(narrow.set_reg('a', 200), Err(Fault::Overflow));
+        assert_eq!(*narrow.get_reg('a'), 0);
+
+        holder.set_reg('a', 200).unwrap();
+        assert_eq!(*holder.get_reg('a'), 200);
+    }
+
+    #[test]
+    fn checked() {
+        let holder : RegisterHolder = RegisterHolder::with_word(8, Overflow::Checked);
+        assert_eq!(holder.add(100, 27), Ok(127));
+        assert_eq!(holder.add(100, 28), Err(Fault::Overflow));
+        assert_eq!(holder.mul(-64, 2), Ok(-128));
+        assert_eq!(holder.rem(7, 0), Err(Fault::DivideByZero));
+    }
+
+    #[test]
+    fn machine_word_width() {
+        let mut machine = Machine::new(Program::load("set a 100\nadd a a\nadd a a"));
+        *machine.registers_mut() = RegisterHolder::with_word(8, Overflow::Wrap);
+        machine.run();
+        assert_eq!(*machine.registers().get_reg('a'), -112);
+    }
+}
@@ -0,0 +1,531 @@
+use super::*;
+use std::error::Error;
+
+// The binary format starts with these bytes and then the version. Bump the version whenever the
+// encoding changes, so that stale caches are rejected instead of misread.
+const MAGIC : &[u8] = b"AOCI";
+pub const FORMAT_VERSION : u8 = 1;
+
+// Opcodes in the binary format, in the order the duet instructions are declared.
+const OPCODES : [&str ; 9] = ["snd", "set", "add", "sub", "mul", "mod", "rcv", "jgz", "jnz"];
+
+const TAG_REGISTER : u8 = 0;
+const TAG_VALUE : u8 = 1;
+
+// How deeply JSON objects and arrays can nest. A program needs three levels: the object, the
+// instruction list and each instruction. The parser recurses, so this keeps hostile input from
+// overflowing the stack.
+const JSON_DEPTH : usize = 3;
+
+// Why a serialized program couldn't be loaded. Positions are byte offsets into the input, except
+// for problems with a JSON instruction, which give the instruction's index.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    BadMagic,
+
+    // Kept whole, since JSON versions can be any number.
+    UnsupportedVersion(i64),
+    UnexpectedEnd,
+    TrailingData(usize),
+    UnknownOpcode(usize, String),
+    BadOperand(usize),
+    InvalidRegister(usize, String),
+
+    // The instruction at the index jumps to the target, which is outside the program. Jumping to
+    // exactly the end is allowed, since that's a normal way to halt.
+    JumpOutOfRange(usize, i64),
+
+    Json(usize, String),
+}
+
+// The subset of JSON that programs use. Numbers are always integers.
+#[derive(Clone, PartialEq, Debug)]
+enum Json {
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+struct Reader<'b> {
+    bytes : &'b [u8],
+    at : usize,
+}
+
+impl<'b> Reader<'b> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self.bytes.get(self.at).ok_or(DecodeError::UnexpectedEnd)?;
+        self.at += 1;
+        Ok(byte)
+    }
+
+    // An unsigned LEB128 number.
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let start = self.at;
+        let mut value = 0u64;
+        for shift in (0 .. 64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::BadOperand(start))
+    }
+
+    fn register(&mut self) -> Result<char, DecodeError> {
+        let at = self.at;
+        let reg = self.byte()? as char;
+        if reg.is_ascii_alphabetic() {
+            Ok(reg)
+        } else {
+            Err(DecodeError::InvalidRegister(at, reg.to_string()))
+        }
+    }
+
+    fn operand(&mut self) -> Result<RegisterOrValue, DecodeError> {
+        let at = self.at;
+        match self.byte()? {
+            TAG_REGISTER => self.register().map(RegisterOrValue::Reg),
+            TAG_VALUE => {
+                // Zigzag encoded, so small negative numbers stay small.
+                let zigzag = self.varint()?;
+                Ok(RegisterOrValue::Val(((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64)))
+            },
+            _ => Err(DecodeError::BadOperand(at)),
+        }
+    }
+}
+
+fn write_varint(out : &mut Vec<u8>, mut value : u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_operand(out : &mut Vec<u8>, rv : &RegisterOrValue) {
+    match *rv {
+        RegisterOrValue::Reg(reg) => out.extend_from_slice(&[TAG_REGISTER, reg as u8]),
+        RegisterOrValue::Val(value) => {
+            out.push(TAG_VALUE);
+            write_varint(out, ((value << 1) ^ (value >> 63)) as u64);
+        },
+    }
+}
+
+// The instruction's opcode and operands, which is all either format stores.
+fn operands(instruction : &Instruction) -> (&'static str, Vec<RegisterOrValue>) {
+    let reg = RegisterOrValue::Reg;
+    match *instruction {
+        Instruction::Snd(ref rv) => ("snd", vec![rv.clone()]),
+        Instruction::Set(r, ref rv) => ("set", vec![reg(r), rv.clone()]),
+        Instruction::Add(r, ref rv) => ("add", vec![reg(r), rv.clone()]),
+        Instruction::Sub(r, ref rv) => ("sub", vec![reg(r), rv.clone()]),
+        Instruction::Mul(r, ref rv) => ("mul", vec![reg(r), rv.clone()]),
+        Instruction::Mod(r, ref rv) => ("mod", vec![reg(r), rv.clone()]),
+        Instruction::Rcv(r) => ("rcv", vec![reg(r)]),
+        Instruction::Jgz(ref cond, ref offset) => ("jgz", vec![cond.clone(), offset.clone()]),
+        Instruction::Jnz(ref cond, ref offset) => ("jnz", vec![cond.clone(), offset.clone()]),
+    }
+}
+
+// Builds an instruction from its opcode and operands. Returns the index of the bad operand if one
+// needs to be a register and isn't, or None for an unknown opcode.
+fn build(opcode : &str, args : Vec<RegisterOrValue>) -> Result<Instruction, Option<usize>> {
+    let arity = match opcode {
+        "snd" | "rcv" => 1,
+        "set" | "add" | "sub" | "mul" | "mod" | "jgz" | "jnz" => 2,
+        _ => return Err(None),
+    };
+
+    if args.len() != arity {
+        return Err(Some(args.len().min(arity)));
+    }
+
+    let mut args = args.into_iter();
+    let first = args.next().unwrap();
+    let second = args.next();
+    let dest = match first {
+        RegisterOrValue::Reg(r) => Ok(r),
+        RegisterOrValue::Val(_) => Err(Some(0)),
+    };
+
+    Ok(match opcode {
+        "snd" => Instruction::Snd(first),
+        "rcv" => Instruction::Rcv(dest?),
+        "set" => Instruction::Set(dest?, second.unwrap()),
+        "add" => Instruction::Add(dest?, second.unwrap()),
+        "sub" => Instruction::Sub(dest?, second.unwrap()),
+        "mul" => Instruction::Mul(dest?, second.unwrap()),
+        "mod" => Instruction::Mod(dest?, second.unwrap()),
+        "jgz" => Instruction::Jgz(first, second.unwrap()),
+        _ => Instruction::Jnz(first, second.unwrap()),
+    })
+}
+
+// Checks that every jump with a constant offset lands in the program or just past its end.
+fn validate(program : Program) -> Result<Program, DecodeError> {
+    let len = program.instructions.len() as i64;
+    for (i, instruction) in program.instructions.iter().enumerate() {
+        if let Op::Jump(_, RegisterOrValue::Val(offset)) = instruction.op() {
+            // Too far to even represent is as out of range as it gets.
+            let target = (i as i64).checked_add(offset).unwrap_or(i64::MAX);
+            if target < 0 || target > len {
+                return Err(DecodeError::JumpOutOfRange(i, target));
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+impl Program {
+    // The versioned binary encoding: the magic bytes, the version, the number of instructions,
+    // then each instruction as its opcode's index followed by its operands. Registers are a tag
+    // byte and the letter; values are a tag byte and a zigzag LEB128 number.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(FORMAT_VERSION);
+        write_varint(&mut out, self.instructions.len() as u64);
+        for instruction in &self.instructions {
+            let (opcode, args) = operands(instruction);
+            out.push(OPCODES.iter().position(|o| *o == opcode).unwrap() as u8);
+            for arg in &args {
+                write_operand(&mut out, arg);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes : &[u8]) -> Result<Program, DecodeError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(DecodeError::BadMagic);
+        }
+
+        let mut reader = Reader { bytes, at : MAGIC.len() };
+        let version = reader.byte()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(i64::from(version)));
+        }
+
+        let count = reader.varint()?;
+        let mut instructions = vec![];
+        for _ in 0 .. count {
+            let at = reader.at;
+            let code = reader.byte()?;
+            let opcode = *OPCODES.get(code as usize).ok_or_else(|| DecodeError::UnknownOpcode(at, code.to_string()))?;
+            let arity = if opcode == "snd" || opcode == "rcv" { 1 } else { 2 };
+            let mut args = vec![];
+            for _ in 0 .. arity {
+                args.push(reader.operand()?);
+            }
+
+            instructions.push(build(opcode, args).map_err(|_| DecodeError::BadOperand(at))?);
+        }
+
+        if reader.at != bytes.len() {
+            return Err(DecodeError::TrailingData(reader.at));
+        }
+
+        validate(Program { instructions })
+    }
+
+    // The program as JSON for other tools, like
+    // {"version":1,"instructions":[["set","a",5],["jgz","a",-1]]}
+    // with registers as strings and values as numbers.
+    pub fn to_json(&self) -> String {
+        let instructions : Vec<String> = self.instructions.iter().map(|instruction| {
+            let (opcode, args) = operands(instruction);
+            let mut fields = vec![format!("\"{}\"", opcode)];
+            fields.extend(args.iter().map(|arg| match *arg {
+                RegisterOrValue::Reg(reg) => format!("\"{}\"", reg),
+                RegisterOrValue::Val(value) => value.to_string(),
+            }));
+            format!("[{}]", fields.join(","))
+        }).collect();
+
+        format!("{{\"version\":{},\"instructions\":[{}]}}", FORMAT_VERSION, instructions.join(","))
+    }
+
+    pub fn from_json(input : &str) -> Result<Program, DecodeError> {
+        let mut parser = JsonParser { input : input.as_bytes(), at : 0, depth : 0 };
+        let json = parser.value()?;
+        parser.whitespace();
+        if parser.at != input.len() {
+            return Err(DecodeError::TrailingData(parser.at));
+        }
+
+        let fields = match json {
+            Json::Object(fields) => fields,
+            _ => return Err(DecodeError::Json(0, String::from("expected an object"))),
+        };
+
+        let field = |name : &str| fields.iter().find(|f| f.0 == name).map(|f| &f.1);
+        match field("version") {
+            Some(&Json::Number(version)) if version == i64::from(FORMAT_VERSION) => {},
+            Some(&Json::Number(version)) => return Err(DecodeError::UnsupportedVersion(version)),
+            _ => return Err(DecodeError::Json(0, String::from("expected a version"))),
+        }
+
+        let items = match field("instructions") {
+            Some(Json::Array(items)) => items,
+            _ => return Err(DecodeError::Json(0, String::from("expected instructions"))),
+        };
+
+        let mut instructions = vec![];
+        for (i, item) in items.iter().enumerate() {
+            let (opcode, args) = match *item {
+                Json::Array(ref parts) if !parts.is_empty() => (&parts[0], &parts[1 ..]),
+                _ => return Err(DecodeError::Json(i, String::from("expected an instruction array"))),
+            };
+
+            let opcode = match *opcode {
+                Json::String(ref opcode) => opcode.as_str(),
+                _ => return Err(DecodeError::UnknownOpcode(i, format!("{:?}", opcode))),
+            };
+
+            let mut operands = vec![];
+            for arg in args {
+                operands.push(match *arg {
+                    Json::Number(value) => RegisterOrValue::Val(value),
+                    Json::String(ref name) if RE_REGISTER.is_match(name) => RegisterOrValue::Reg(name.chars().next().unwrap()),
+                    Json::String(ref name) => return Err(DecodeError::InvalidRegister(i, name.clone())),
+                    _ => return Err(DecodeError::BadOperand(i)),
+                });
+            }
+
+            instructions.push(build(opcode, operands).map_err(|bad| match bad {
+                Some(_) => DecodeError::BadOperand(i),
+                None => DecodeError::UnknownOpcode(i, String::from(opcode)),
+            })?);
+        }
+
+        validate(Program { instructions })
+    }
+}
+
+struct JsonParser<'i> {
+    input : &'i [u8],
+    at : usize,
+    depth : usize,
+}
+
+impl<'i> JsonParser<'i> {
+    fn error<T>(&self, expected : &str) -> Result<T, DecodeError> {
+        Err(DecodeError::Json(self.at, format!("expected {}", expected)))
+    }
+
+    fn whitespace(&mut self) {
+        while self.input.get(self.at).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, c : u8) -> bool {
+        self.whitespace();
+        if self.input.get(self.at) == Some(&c) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // A comma separated list of items up to the closing character.
+    fn list<T, F>(&mut self, close : u8, mut item : F) -> Result<Vec<T>, DecodeError>
+    where F : FnMut(&mut Self) -> Result<T, DecodeError> {
+        let mut items = vec![];
+        if self.eat(close) {
+            return Ok(items);
+        }
+
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+
+            if !self.eat(b',') {
+                return self.error(&format!("',' or '{}'", close as char));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        if !self.eat(b'"') {
+            return self.error("a string");
+        }
+
+        let mut text = String::new();
+        loop {
+            match self.input.get(self.at) {
+                None => return Err(DecodeError::UnexpectedEnd),
+                Some(b'"') => {
+                    self.at += 1;
+                    return Ok(text);
+                },
+                Some(b'\\') => {
+                    match self.input.get(self.at + 1) {
+                        Some(&c) if c == b'"' || c == b'\\' || c == b'/' => text.push(c as char),
+                        _ => return self.error("a simple escape"),
+                    }
+                    self.at += 2;
+                },
+                Some(&c) => {
+                    text.push(c as char);
+                    self.at += 1;
+                },
+            }
+        }
+    }
+
+    // An object or array, one level deeper than the current one.
+    fn nested<T, F>(&mut self, close : u8, item : F) -> Result<Vec<T>, DecodeError>
+    where F : FnMut(&mut Self) -> Result<T, DecodeError> {
+        if self.depth == JSON_DEPTH {
+            return Err(DecodeError::Json(self.at, format!("expected at most {} levels of nesting", JSON_DEPTH)));
+        }
+
+        self.at += 1;
+        self.depth += 1;
+        let items = self.list(close, item);
+        self.depth -= 1;
+        items
+    }
+
+    fn value(&mut self) -> Result<Json, DecodeError> {
+        self.whitespace();
+        match self.input.get(self.at) {
+            Some(b'{') => {
+                self.nested(b'}', |p| {
+                    let key = p.string()?;
+                    if !p.eat(b':') {
+                        return p.error("':'");
+                    }
+                    Ok((key, p.value()?))
+                }).map(Json::Object)
+            },
+            Some(b'[') => self.nested(b']', |p| p.value()).map(Json::Array),
+            Some(b'"') => self.string().map(Json::String),
+            Some(&c) if c == b'-' || c.is_ascii_digit() => {
+                let start = self.at;
+                self.at += 1;
+                while self.input.get(self.at).is_some_and(|c| c.is_ascii_digit()) {
+                    self.at += 1;
+                }
+
+                match std::str::from_utf8(&self.input[start .. self.at]).unwrap().parse() {
+                    Ok(value) => Ok(Json::Number(value)),
+                    Err(_) => Err(DecodeError::Json(start, String::from("expected an integer"))),
+                }
+            },
+            Some(_) => self.error("a value"),
+            None => Err(DecodeError::UnexpectedEnd),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::BadMagic => write!(f, "not a serialized program"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported version {}, expected {}", version, FORMAT_VERSION),
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::TrailingData(at) => write!(f, "unexpected data after the program at {}", at),
+            DecodeError::UnknownOpcode(at, ref opcode) => write!(f, "unknown opcode {} at {}", opcode, at),
+            DecodeError::BadOperand(at) => write!(f, "bad operand at {}", at),
+            DecodeError::InvalidRegister(at, ref name) => write!(f, "invalid register `{}` at {}", name, at),
+            DecodeError::JumpOutOfRange(ip, target) => write!(f, "instruction {} jumps to {}, outside the program", ip, target),
+            DecodeError::Json(at, ref message) => write!(f, "invalid JSON at {}: {}", at, message),
+        }
+    }
+}
+
+impl Error for DecodeError {
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn program() -> Program {
+        Program::load(
+r"set a 1
+add a 2
+mul a a
+mod a 5
+snd a
+set a 0
+rcv a
+jgz a -1
+set a -1000000000000
+sub Z 64
+jnz b 2
+jgz 1 b")
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let program = program();
+        let bytes = program.to_bytes();
+        assert_eq!(&bytes[.. 7], b"AOCI\x01\x0c\x01");
+        assert_eq!(&bytes[7 .. 12], &[TAG_REGISTER, b'a', TAG_VALUE, 2, 2]);
+        assert_eq!(Program::from_bytes(&bytes), Ok(program));
+    }
+
+    #[test]
+    fn binary_errors() {
+        let bytes = program().to_bytes();
+        assert_eq!(Program::from_bytes(b"AOCX"), Err(DecodeError::BadMagic));
+        assert_eq!(Program::from_bytes(b"AOCI\x02\x00"), Err(DecodeError::UnsupportedVersion(2)));
+        assert_eq!(Program::from_bytes(&bytes[.. bytes.len() - 1]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(Program::from_bytes(&[&bytes[..], b"x"].concat()), Err(DecodeError::TrailingData(bytes.len())));
+        assert_eq!(Program::from_bytes(b"AOCI\x01\x01\x09"), Err(DecodeError::UnknownOpcode(6, String::from("9"))));
+        assert_eq!(Program::from_bytes(b"AOCI\x01\x01\x06\x00#"), Err(DecodeError::InvalidRegister(8, String::from("#"))));
+        assert_eq!(Program::from_bytes(b"AOCI\x01\x01\x06\x01\x02"), Err(DecodeError::BadOperand(6)));
+        assert_eq!(Program::from_bytes(b"AOCI\x01\x01\x07\x01\x02\x01\x05"), Err(DecodeError::JumpOutOfRange(0, -3)));
+
+        let far = Program::load("set a 1\njgz 1 9223372036854775807\njgz 1 -9223372036854775808");
+        assert_eq!(Program::from_bytes(&far.to_bytes()), Err(DecodeError::JumpOutOfRange(1, i64::MAX)));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let small = Program::load("set a 5\njgz a -1\nrcv b");
+        let json = small.to_json();
+        assert_eq!(json, r#"{"version":1,"instructions":[["set","a",5],["jgz","a",-1],["rcv","b"]]}"#);
+        assert_eq!(Program::from_json(&json), Ok(small));
+
+        let program = program();
+        assert_eq!(Program::from_json(&program.to_json()), Ok(program));
+
+        let spaced = "{ \"instructions\" : [ [\"snd\", 1] ],\n  \"version\" : 1 }\n";
+        assert_eq!(Program::from_json(spaced), Ok(Program::load("snd 1")));
+    }
+
+    #[test]
+    fn json_errors() {
+        let load = |instructions : &str| Program::from_json(&format!("{{\"version\":1,\"instructions\":[{}]}}", instructions));
+        assert_eq!(load(r#"["set","ab",1]"#), Err(DecodeError::InvalidRegister(0, String::from("ab"))));
+        assert_eq!(load(r#"["snd",1],["set",1,1]"#), Err(DecodeError::BadOperand(1)));
+        assert_eq!(load(r#"["set","a"]"#), Err(DecodeError::BadOperand(0)));
+        assert_eq!(load(r#"["hlt"]"#), Err(DecodeError::UnknownOpcode(0, String::from("hlt"))));
+        assert_eq!(load(r#"["jnz",1,2]"#), Err(DecodeError::JumpOutOfRange(0, 2)));
+        assert_eq!(load(r#"["jnz",1,1]"#), Ok(Program::load("jnz 1 1")));
+        assert_eq!(load(r#"["set","a",1],["jgz",1,9223372036854775807]"#), Err(DecodeError::JumpOutOfRange(1, i64::MAX)));
+        assert_eq!(load(r#"["set","a",1],["jgz",1,-9223372036854775808]"#), Err(DecodeError::JumpOutOfRange(1, -9223372036854775807)));
+        assert_eq!(Program::from_json(r#"{"version":2,"instructions":[]}"#), Err(DecodeError::UnsupportedVersion(2)));
+
+        let error = Program::from_json(r#"{"version":257,"instructions":[]}"#).unwrap_err();
+        assert_eq!(error, DecodeError::UnsupportedVersion(257));
+        assert_eq!(error.to_string(), "unsupported version 257, expected 1");
+        assert_eq!(Program::from_json(r#"{"version":1 "instructions":[]}"#), Err(DecodeError::Json(13, String::from("expected ',' or '}'"))));
+
+        let too_deep = String::from("expected at most 3 levels of nesting");
+        assert_eq!(load(r#"["snd",[1]]"#), Err(DecodeError::Json(36, too_deep.clone())));
+        assert_eq!(Program::from_json(&"[".repeat(100000)), Err(DecodeError::Json(3, too_deep)));
+    }
+}
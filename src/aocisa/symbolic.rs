@@ -0,0 +1,309 @@
+use super::*;
+use std::collections::BTreeMap;
+
+// Why a program couldn't be run symbolically.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SymbolicError {
+    // The instruction can't be expressed as a polynomial, like mod of an unknown value, or talks
+    // to the outside world.
+    Unsupported(usize),
+
+    // Whether the jump is taken depends on the initial values, with the condition in terms of
+    // them.
+    Branch(usize, Poly),
+
+    // The program didn't halt within the step limit.
+    StepLimit(u64),
+
+    // A coefficient, or a constant value, at the instruction got too big for an i64.
+    Overflow(usize),
+}
+
+// Runs a program with every register as a polynomial in the initial register values, rather than
+// a number. Control flow has to be known ahead of time, so conditional jumps only work on values
+// that end up constant. Loops with a closed form are summarized, which works even if their trip
+// count isn't constant; the summary then only holds if the trip count is at least 1, which is
+// recorded as an assumption.
+pub struct SymbolicMachine<I> {
+    program : Program<Optimized<I>>,
+    values : BTreeMap<char, Poly>,
+    assumptions : Vec<Poly>,
+    ip : usize,
+    steps : u64,
+}
+
+impl<I> SymbolicMachine<I>
+where I : InstructionSet<Register = char> + Analyze {
+    pub fn new(program : &Program<I>) -> SymbolicMachine<I> {
+        SymbolicMachine {
+            program : optimize(program),
+            values : BTreeMap::new(),
+            assumptions : vec![],
+            ip : 0,
+            steps : 0,
+        }
+    }
+
+    // A register's value in terms of the initial values. Registers start as themselves.
+    pub fn get_reg(&self, reg : char) -> Poly {
+        self.values.get(&reg).cloned().unwrap_or_else(|| Poly::register(reg))
+    }
+
+    // Gives a register a starting value, usually a constant, like a puzzle's part 2 setting a to 1.
+    pub fn set_reg(&mut self, reg : char, value : Poly) {
+        self.values.insert(reg, value);
+    }
+
+    // Every register that isn't simply its initial value anymore.
+    pub fn registers(&self) -> BTreeMap<char, Poly> {
+        self.values.iter().filter(|&(r, value)| *value != Poly::register(*r)).map(|(r, value)| (*r, value.clone())).collect()
+    }
+
+    // Polynomials that have to be at least 1 for the registers to be right.
+    pub fn assumptions(&self) -> &[Poly] {
+        &self.assumptions
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    // Instructions executed, with each summarized loop counting as one.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    fn operand(&self, rv : &RegisterOrValue) -> Poly {
+        match *rv {
+            RegisterOrValue::Reg(r) => self.get_reg(r),
+            RegisterOrValue::Val(v) => Poly::constant(v),
+        }
+    }
+
+    // Tries to run a summarized loop at the ip. Returns false if its guards say it doesn't apply.
+    fn apply_summary(&mut self, summary : &LoopSummary, len : usize) -> Result<bool, SymbolicError> {
+        // Guards known to hold are dropped, and any known not to hold means running the loop
+        // normally. The rest become assumptions.
+        let overflow = SymbolicError::Overflow(self.ip);
+        let substitute = |p : &Poly| p.substitute(|r| self.get_reg(r)).ok_or(overflow.clone());
+        let guards = summary.guards.iter().map(&substitute).collect::<Result<Vec<Poly>, SymbolicError>>()?;
+        let guards : Vec<Poly> = guards.into_iter().filter(|g| g.as_constant().is_none_or(|c| c < 1)).collect();
+        if guards.iter().any(|g| g.as_constant().is_some()) {
+            return Ok(false);
+        }
+
+        let updates = summary.updates.iter().map(|(r, p)| substitute(p).map(|p| (*r, p))).collect::<Result<Vec<(char, Poly)>, SymbolicError>>()?;
+        for guard in guards {
+            if !self.assumptions.contains(&guard) {
+                self.assumptions.push(guard);
+            }
+        }
+
+        self.values.extend(updates);
+        self.ip += len;
+        Ok(true)
+    }
+
+    fn apply_op(&mut self, op : &Op) -> Result<(), SymbolicError> {
+        let ip = self.ip;
+        let (reg, value) = match *op {
+            Op::Set(reg, ref rv) => (reg, Some(self.operand(rv))),
+            Op::Add(reg, ref rv) => (reg, self.get_reg(reg).checked_add(self.operand(rv))),
+            Op::Sub(reg, ref rv) => (reg, self.get_reg(reg).checked_sub(self.operand(rv))),
+            Op::Mul(reg, ref rv) => (reg, self.get_reg(reg).checked_mul(self.operand(rv))),
+            Op::Mod(reg, ref rv) => {
+                match (self.get_reg(reg).as_constant(), self.operand(rv).as_constant()) {
+                    (Some(a), Some(b)) if b != 0 => (reg, a.checked_rem(b).map(Poly::constant)),
+                    _ => return Err(SymbolicError::Unsupported(ip)),
+                }
+            },
+            Op::Jump(ref cond, ref offset) => {
+                let value = self.operand(cond.operand());
+                let taken = match (cond, value.as_constant()) {
+                    (&Condition::NonZero(_), Some(v)) => v != 0,
+                    (&Condition::Positive(_), Some(v)) => v > 0,
+                    (_, None) => return Err(SymbolicError::Branch(ip, value)),
+                };
+
+                self.ip = if taken {
+                    match self.operand(offset).as_constant() {
+                        Some(offset) => offset_ip(ip, offset),
+                        None => return Err(SymbolicError::Unsupported(ip)),
+                    }
+                } else {
+                    ip + 1
+                };
+
+                return Ok(());
+            },
+            Op::Nop => {
+                self.ip += 1;
+                return Ok(());
+            },
+            Op::Send(_) | Op::Receive(_) | Op::Toggle(_) => return Err(SymbolicError::Unsupported(ip)),
+        };
+
+        let value = value.ok_or(SymbolicError::Overflow(ip))?;
+        self.values.insert(reg, value);
+        self.ip += 1;
+        Ok(())
+    }
+
+    // Runs until the program halts. On error, the machine is left at the instruction that caused
+    // it.
+    pub fn run(&mut self, max_steps : u64) -> Result<(), SymbolicError> {
+        while let Some(instruction) = self.program.instructions.get(self.ip).cloned() {
+            if self.steps == max_steps {
+                return Err(SymbolicError::StepLimit(max_steps));
+            }
+
+            let summarized = match instruction {
+                Optimized::Loop { ref summary, len, .. } => self.apply_summary(summary, len)?,
+                Optimized::Plain(_) => false,
+            };
+
+            if !summarized {
+                self.apply_op(&instruction.original().op())?;
+            }
+
+            self.steps += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I> fmt::Display for SymbolicMachine<I>
+where I : InstructionSet<Register = char> + Analyze {
+    // One line per changed register, then the assumptions.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (reg, value) in self.registers() {
+            writeln!(f, "{} = {}", reg, value)?;
+        }
+
+        for assumption in &self.assumptions {
+            writeln!(f, "assuming {} >= 1", assumption)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SymbolicError::Unsupported(ip) => write!(f, "instruction {} can't be run symbolically", ip),
+            SymbolicError::Branch(ip, ref condition) => write!(f, "jump at instruction {} depends on {}", ip, condition),
+            SymbolicError::StepLimit(steps) => write!(f, "didn't halt within {} steps", steps),
+            SymbolicError::Overflow(ip) => write!(f, "value at instruction {} overflows", ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constant_trips() {
+        let mut machine = SymbolicMachine::new(&Program::load(
+r"set c 4
+add a b
+sub c 1
+jgz c -2
+mul a a
+set d a
+mod d 1"));
+        assert_eq!(machine.run(100), Err(SymbolicError::Unsupported(6)));
+        assert_eq!(format!("{}", machine), "a = a*a + 8*a*b + 16*b*b\nc = 0\nd = a*a + 8*a*b + 16*b*b\n");
+        assert!(machine.assumptions().is_empty());
+    }
+
+    #[test]
+    fn unknown_trips() {
+        let mut machine = SymbolicMachine::new(&Program::load(
+r"add a b
+sub c 1
+jgz c -2
+add a 1"));
+        assert_eq!(machine.run(100), Ok(()));
+        assert_eq!(format!("{}", machine), "a = b*c + a + 1\nc = 0\nassuming c >= 1\n");
+        assert_eq!(machine.steps(), 2);
+    }
+
+    #[test]
+    fn unrolled() {
+        // The loop has a mod in it, so it has no closed form, but its trip count is constant so it
+        // can run one step at a time.
+        let mut machine = SymbolicMachine::new(&Program::load(
+r"set c 3
+set d c
+mod d 2
+mul a 2
+add a d
+sub c 1
+jgz c -5"));
+        assert_eq!(machine.run(1000), Ok(()));
+        assert_eq!(format!("{}", machine.get_reg('a')), "8*a + 5");
+        assert_eq!(machine.steps(), 19);
+
+        let mut machine = SymbolicMachine::new(&Program::load("set c 3\njgz 1 0"));
+        assert_eq!(machine.run(1000), Err(SymbolicError::StepLimit(1000)));
+    }
+
+    #[test]
+    fn overflow() {
+        let mut machine = SymbolicMachine::new(&Program::load("set a 9223372036854775807\nadd a 1"));
+        assert_eq!(machine.run(100), Err(SymbolicError::Overflow(1)));
+        assert_eq!(machine.get_reg('a'), Poly::constant(i64::MAX));
+
+        let mut machine = SymbolicMachine::new(&Program::load("set a -9223372036854775808\nmod a -1"));
+        assert_eq!(machine.run(100), Err(SymbolicError::Overflow(1)));
+
+        let mut machine = SymbolicMachine::new(&Program::load("mul a 4611686018427387904\nmul a 2"));
+        assert_eq!(machine.run(100), Err(SymbolicError::Overflow(1)));
+        assert_eq!(format!("{}", SymbolicError::Overflow(1)), "value at instruction 1 overflows");
+
+        // The loop's summary is fine, but putting the starting values into it overflows.
+        let mut machine = SymbolicMachine::new(&Program::load("set b 4611686018427387904\nset c 2\nadd a b\nsub c 1\njgz c -2"));
+        assert_eq!(machine.run(100), Err(SymbolicError::Overflow(2)));
+    }
+
+    #[test]
+    fn branch_on_unknown() {
+        let program : Program<Assembunny> =
+r"cpy 1 a
+cpy 1 b
+cpy 26 d
+jnz c 2
+jnz 1 5
+cpy 7 c
+inc d
+dec c
+jnz c -2
+cpy a c
+inc a
+dec b
+jnz b -2
+cpy c b
+dec d
+jnz d -6
+cpy 13 c
+cpy 14 d
+inc a
+dec d
+jnz d -2
+dec c
+jnz c -5".parse().unwrap();
+
+        let mut machine = SymbolicMachine::new(&program);
+        assert_eq!(machine.run(1000), Err(SymbolicError::Branch(3, Poly::register('c'))));
+
+        // 2016 day 12, solved without running the millions of steps it takes normally.
+        let mut machine = SymbolicMachine::new(&program);
+        machine.set_reg('c', Poly::constant(1));
+        assert_eq!(machine.run(10000), Ok(()));
+        assert_eq!(machine.get_reg('a'), Poly::constant(9227647));
+        assert!(machine.steps() < 1000);
+    }
+}
@@ -0,0 +1,200 @@
+use super::*;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TraceResult {
+    // The value written to a register or sent.
+    Value(i64),
+
+    // Whether a conditional jump was taken.
+    Branch(bool),
+
+    // The instruction had no visible effect.
+    Nothing,
+}
+
+// One executed instruction, along with the values its operands had at the time it ran. For
+// instructions that modify a register, the first operand value is the register's old value.
+pub struct TraceStep<'t, I = Instruction>
+where I : 't {
+    pub instruction : &'t I,
+    pub operands : &'t [i64],
+    pub result : TraceResult,
+}
+
+pub trait Tracer<I = Instruction> {
+    fn trace(&mut self, step : &TraceStep<I>);
+}
+
+// Discards everything. This is what RegisterHolder uses when no tracer is supplied.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct NullTracer;
+
+// Prints every step to stderr.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct StderrTracer;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceRecord<I = Instruction> {
+    pub instruction : I,
+    pub operands : Vec<i64>,
+    pub result : TraceResult,
+}
+
+// Keeps only the most recent steps, so they can be dumped after something goes wrong.
+#[derive(Clone, Debug)]
+pub struct RingBufferTracer<I = Instruction> {
+    capacity : usize,
+    records : VecDeque<TraceRecord<I>>,
+}
+
+impl fmt::Display for TraceResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceResult::Value(v) => write!(f, "{}", v),
+            TraceResult::Branch(b) => write!(f, "{}", b),
+            TraceResult::Nothing => write!(f, "()"),
+        }
+    }
+}
+
+impl<'t, I> fmt::Display for TraceStep<'t, I>
+where I : InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        I::fmt_step(self, f)
+    }
+}
+
+impl<I> Tracer<I> for NullTracer {
+    #[inline(always)]
+    fn trace(&mut self, _step : &TraceStep<I>) {
+    }
+}
+
+impl<I> Tracer<I> for StderrTracer
+where I : InstructionSet {
+    fn trace(&mut self, step : &TraceStep<I>) {
+        eprintln!("  {}", step);
+    }
+}
+
+impl<I> TraceRecord<I> {
+    pub fn as_step(&self) -> TraceStep<'_, I> {
+        TraceStep {
+            instruction : &self.instruction,
+            operands : &self.operands,
+            result : self.result,
+        }
+    }
+}
+
+impl<I> fmt::Display for TraceRecord<I>
+where I : InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_step())
+    }
+}
+
+impl<I> RingBufferTracer<I> {
+    pub fn new(capacity : usize) -> RingBufferTracer<I> {
+        RingBufferTracer {
+            capacity,
+            records : VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // Oldest first.
+    pub fn records(&self) -> std::collections::vec_deque::Iter<'_, TraceRecord<I>> {
+        self.records.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+impl<I> Tracer<I> for RingBufferTracer<I>
+where I : Clone {
+    fn trace(&mut self, step : &TraceStep<I>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(TraceRecord {
+            instruction : step.instruction.clone(),
+            operands : step.operands.to_vec(),
+            result : step.result,
+        });
+    }
+}
+
+impl<I> fmt::Display for RingBufferTracer<I>
+where I : InstructionSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ret = write!(f, "");
+        for record in &self.records {
+            ret = writeln!(f, "{}", record);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_steps() {
+        let mut tracer = RingBufferTracer::new(10);
+        let mut holder = RegisterHolder::default();
+        holder.apply_instruction_traced(&Instruction::Set('a', RegisterOrValue::Val(5)), &mut tracer);
+        holder.apply_instruction_traced(&Instruction::Add('a', RegisterOrValue::Val(3)), &mut tracer);
+        holder.get_next_ip_traced(&Instruction::Jgz(RegisterOrValue::Reg('a'), RegisterOrValue::Val(2)), 0, &mut tracer);
+        holder.get_next_ip_traced(&Instruction::Jnz(RegisterOrValue::Reg('b'), RegisterOrValue::Reg('a')), 0, &mut tracer);
+
+        assert_eq!(format!("{}", tracer),
+r"a <= 5
+add a (5) 3 => 8
+jgz a (8) 2 (2) => true
+jnz b (0) a (8) => false
+");
+    }
+
+    #[test]
+    fn ring_buffer_keeps_last() {
+        let mut tracer = RingBufferTracer::new(2);
+        let mut holder = RegisterHolder::default();
+        for _ in 0 .. 5 {
+            holder.apply_instruction_traced(&Instruction::Add('a', RegisterOrValue::Val(1)), &mut tracer);
+        }
+
+        let results : Vec<TraceResult> = tracer.records().map(|r| r.result).collect();
+        assert_eq!(results, vec![TraceResult::Value(4), TraceResult::Value(5)]);
+    }
+
+    #[test]
+    fn ring_buffer_empty() {
+        let mut tracer = RingBufferTracer::new(0);
+        let mut holder = RegisterHolder::default();
+        holder.apply_instruction_traced(&Instruction::Add('a', RegisterOrValue::Val(1)), &mut tracer);
+        assert_eq!(tracer.records().count(), 0);
+    }
+
+    #[test]
+    fn machine_traces_messages() {
+        let mut machine = Machine::with_tracer(Program::load(
+r"snd 7
+rcv b"), RingBufferTracer::new(10));
+        machine.push_input(3);
+        machine.run();
+
+        assert_eq!(format!("{}", machine.tracer()),
+r"snd 7 (7)
+rcv b <= 3
+");
+    }
+}
@@ -0,0 +1,81 @@
+// An interactive debugger for aocisa programs.
+//
+// usage: aocdbg [--assembunny] <program file>
+//
+// Reads commands from stdin. An empty line repeats the last command. "help" lists the commands.
+
+extern crate aoclib;
+
+use aoclib::aocisa::*;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+
+fn load<I>(path : &str) -> Program<I>
+where I : InstructionSet {
+    let input = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("can't read {}: {}", path, e);
+        process::exit(1);
+    });
+
+    input.trim_end().parse().unwrap_or_else(|errors : Vec<ParseError>| {
+        for e in errors {
+            eprintln!("{}: {}", path, e);
+        }
+
+        process::exit(1);
+    })
+}
+
+fn repl<I>(mut debugger : Debugger<I>)
+where I : InstructionSet<Register = char> + Analyze {
+    println!("{}", debugger.location());
+
+    let stdin = io::stdin();
+    let mut last_command = None;
+    loop {
+        print!("(aocdbg) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        let command = if line.trim().is_empty() {
+            match last_command.clone() {
+                Some(command) => command,
+                None => continue,
+            }
+        } else {
+            match line.parse::<Command>() {
+                Ok(command) => command,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                },
+            }
+        };
+
+        if command == Command::Quit {
+            break;
+        }
+
+        println!("{}", debugger.execute(&command));
+        last_command = Some(command);
+    }
+}
+
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(|a| a.as_str()).collect::<Vec<&str>>().as_slice() {
+        ["--assembunny", path] => repl(Debugger::new(load::<Assembunny>(path))),
+        [path] if !path.starts_with("--") => repl(Debugger::new(load::<Instruction>(path))),
+        _ => {
+            eprintln!("usage: aocdbg [--assembunny] <program file>");
+            process::exit(2);
+        },
+    }
+}
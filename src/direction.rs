@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Direction {
     Up,
     Down,
@@ -9,6 +9,8 @@ pub enum Direction {
 }
 
 impl Direction {
+    pub const ALL : [Direction; 4] = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+
     pub fn turn_right(&self) -> Direction {
         match *self {
             Direction::Up => Direction::Right,
@@ -44,6 +46,27 @@ impl Direction {
             Direction::Right => (1, 0),
         }
     }
+
+    // Unlike step_offset, y grows downward here, to match Grid.
+    pub fn grid_offset(&self) -> (i32, i32) {
+        match *self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    // The direction that grid_offset gives this offset, if any.
+    pub fn from_grid_offset(offset : (i32, i32)) -> Option<Direction> {
+        match offset {
+            (0, -1) => Some(Direction::Up),
+            (0, 1) => Some(Direction::Down),
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            _ => None,
+        }
+    }
 }
 
 
@@ -78,6 +101,16 @@ mod test {
         assert_eq!(Direction::Right.turn_left(), Direction::Up);
     }
 
+    #[test]
+    fn grid_offset() {
+        for direction in &Direction::ALL {
+            assert_eq!(Direction::from_grid_offset(direction.grid_offset()), Some(*direction));
+            assert_eq!(direction.grid_offset().1, -direction.step_offset().1);
+        }
+
+        assert_eq!(Direction::from_grid_offset((1, 1)), None);
+    }
+
     #[test]
     fn reverse() {
         assert_eq!(Direction::Up.reverse(), Direction::Down);
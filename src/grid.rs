@@ -1,5 +1,9 @@
 use std;
+use std::convert::TryFrom;
 use std::fmt;
+use std::ops::{Index, IndexMut};
+use point::Point;
+use direction::Direction;
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Grid<T> {
@@ -7,14 +11,95 @@ pub struct Grid<T> {
     size_x : usize,
 }
 
+// Why some puzzle text couldn't be turned into a grid. Lines and columns are both 1-based and count
+// from the start of the whole input, so they still point at the right place when it holds several
+// grids.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GridParseError {
+    BadChar { line : usize, column : usize, ch : char },
+
+    // Lines have to be as long as the first line of their grid unless padding was given.
+    RaggedLine { line : usize, len : usize, expected : usize },
+}
+
+// The smallest rectangle holding a set of points. Both corners are inclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BoundingBox {
+    pub min_x : i32,
+    pub min_y : i32,
+    pub max_x : i32,
+    pub max_y : i32,
+}
+
+// Anything holding values at signed coordinates, so that algorithms can work on both the dense Grid
+// and the unbounded SparseGrid. As with Grid, y grows downward when displayed.
+pub trait Plane<T> {
+    // The region worth looking at, or None if there's nothing in it.
+    fn bounding_box(&self) -> Option<BoundingBox>;
+
+    // None if the point can't hold a value, like off the edge of a dense grid.
+    fn at(&self, x : i32, y : i32) -> Option<&T>;
+
+    fn at_mut(&mut self, x : i32, y : i32) -> Option<&mut T>;
+
+    fn count_where<P>(&self, mut predicate : P) -> usize
+    where P : FnMut(&T) -> bool {
+        self.bounding_box().map_or(0, |bounds| {
+            bounds.points().filter(|&(x, y)| self.at(x, y).is_some_and(&mut predicate)).count()
+        })
+    }
+}
+
+// Which cells around a location count as its neighbours.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stencil<'s> {
+    // Up, left, right and down.
+    Orthogonal,
+
+    Diagonal,
+
+    // The 8 surrounding cells.
+    All,
+
+    // The 6 cells touching a hexagon, for hex grids stored with odd rows shifted half a cell right.
+    HexOffset,
+
+    // Offsets from the location, with y growing downward.
+    Custom(&'s [(i32, i32)]),
+}
+
+// One neighbour of a location. The direction is set when it's a single orthogonal step away.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Neighbour<'t, T>
+where T : 't {
+    pub location : (usize, usize),
+    pub direction : Option<Direction>,
+    pub value : &'t T,
+}
+
+pub struct Neighbours<'t, T>
+where T : 't {
+    grid : &'t Grid<T>,
+    x : usize,
+    y : usize,
+    offsets : &'t [(i32, i32)],
+    wrap : bool,
+    pos : usize,
+}
+
 pub struct GridIterator<'t, T>
 where T : 't {
     grid : &'t Grid<T>,
     iter : std::iter::Enumerate<std::slice::Iter<'t, T>>,
 }
 
+impl<T> Default for Grid<T> {
+    fn default() -> Grid<T> {
+        Grid::new()
+    }
+}
+
 impl<T> Grid<T> {
-    #![allow(new_without_default_derive)]
     pub fn new() -> Grid<T> {
         Grid {
             grid : vec![],
@@ -30,12 +115,81 @@ impl<T> Grid<T> {
         grid
     }
 
+    // One row per line, and one element per char of it. Blank lines before and after the grid are
+    // ignored.
+    pub fn parse(input : &str) -> Result<Grid<T>, GridParseError>
+    where T : TryFrom<char> {
+        Grid::parse_with(input, |ch| T::try_from(ch).ok())
+    }
+
+    pub fn parse_with<F>(input : &str, convert : F) -> Result<Grid<T>, GridParseError>
+    where F : FnMut(char) -> Option<T> {
+        Grid::parse_lines(&trim_blank_lines(numbered_lines(input)), convert, None::<fn() -> T>)
+    }
+
+    // Like parse, but short lines are filled out to the longest line with padding, for inputs whose
+    // trailing spaces got stripped.
+    pub fn parse_padded(input : &str, padding : T) -> Result<Grid<T>, GridParseError>
+    where T : TryFrom<char> + Clone {
+        Grid::parse_padded_with(input, padding, |ch| T::try_from(ch).ok())
+    }
+
+    pub fn parse_padded_with<F>(input : &str, padding : T, convert : F) -> Result<Grid<T>, GridParseError>
+    where T : Clone,
+          F : FnMut(char) -> Option<T> {
+        Grid::parse_lines(&trim_blank_lines(numbered_lines(input)), convert, Some(|| padding.clone()))
+    }
+
+    // Several grids separated by blank lines, like the tiles or patterns some puzzles give.
+    pub fn parse_all(input : &str) -> Result<Vec<Grid<T>>, GridParseError>
+    where T : TryFrom<char> {
+        Grid::parse_all_with(input, |ch| T::try_from(ch).ok())
+    }
+
+    pub fn parse_all_with<F>(input : &str, mut convert : F) -> Result<Vec<Grid<T>>, GridParseError>
+    where F : FnMut(char) -> Option<T> {
+        let lines = numbered_lines(input);
+        lines.split(|&(_, line)| line.is_empty()).filter(|block| !block.is_empty()).map(|block| {
+            Grid::parse_lines(block, &mut convert, None::<fn() -> T>)
+        }).collect()
+    }
+
+    fn parse_lines<F, P>(lines : &[(usize, &str)], mut convert : F, padding : Option<P>) -> Result<Grid<T>, GridParseError>
+    where F : FnMut(char) -> Option<T>,
+          P : Fn() -> T {
+        let width = match padding {
+            Some(_) => lines.iter().map(|&(_, line)| line.chars().count()).max().unwrap_or(0),
+            None => lines.first().map_or(0, |&(_, line)| line.chars().count()),
+        };
+
+        let mut grid = Grid::new();
+        for &(number, line) in lines {
+            let mut row = Vec::with_capacity(width);
+            for (index, ch) in line.chars().enumerate() {
+                match convert(ch) {
+                    Some(value) => row.push(value),
+                    None => return Err(GridParseError::BadChar { line : number, column : index + 1, ch }),
+                }
+            }
+
+            match padding {
+                Some(ref pad) => row.resize_with(width, pad),
+                None if row.len() != width => return Err(GridParseError::RaggedLine { line : number, len : row.len(), expected : width }),
+                None => {},
+            }
+
+            grid.add_row(row);
+        }
+
+        Ok(grid)
+    }
+
     pub fn size_x(&self) -> usize {
         self.size_x
     }
 
     pub fn size_y(&self) -> usize {
-        self.grid.len() / self.size_x
+        self.grid.len().checked_div(self.size_x).unwrap_or(0)
     }
 
     pub fn add_row(&mut self, mut row : Vec<T>) {
@@ -63,11 +217,30 @@ impl<T> Grid<T> {
         }
     }
 
-    fn index_for_location(&self, x : usize, y : usize) -> usize {
-        if x < self.size_x {
-            (y * self.size_x) + x
+    fn index_for_location(&self, x : usize, y : usize) -> Option<usize> {
+        if x < self.size_x && y < self.size_y() {
+            Some((y * self.size_x) + x)
         } else {
-            panic!("column out of range! needs < {}", self.size_x);
+            None
+        }
+    }
+
+    fn index_for_signed_location(&self, x : i64, y : i64) -> Option<usize> {
+        if x >= 0 && y >= 0 {
+            self.index_for_location(x as usize, y as usize)
+        } else {
+            None
+        }
+    }
+
+    // Wraps around both edges, as if the grid were tiled forever in every direction.
+    fn index_for_wrapped_location(&self, x : i64, y : i64) -> Option<usize> {
+        if self.grid.is_empty() {
+            None
+        } else {
+            let x = x.rem_euclid(self.size_x as i64) as usize;
+            let y = y.rem_euclid(self.size_y() as i64) as usize;
+            self.index_for_location(x, y)
         }
     }
 
@@ -75,31 +248,95 @@ impl<T> Grid<T> {
         ((index % self.size_x), (index / self.size_x))
     }
 
-    // 0, 0 is the upper left corner
+    // 0, 0 is the upper left corner. None if either coordinate is off the edge.
     pub fn get(&self, x : usize, y : usize) -> Option<&T> {
-        self.grid.get(self.index_for_location(x, y))
+        self.index_for_location(x, y).map(|index| &self.grid[index])
     }
 
     pub fn get_mut(&mut self, x : usize, y : usize) -> Option<&mut T> {
-        let index = self.index_for_location(x, y);
-        self.grid.get_mut(index)
+        self.index_for_location(x, y).map(move |index| &mut self.grid[index])
+    }
+
+    // The value dx, dy away from x, y, or None if that's off the edge, so neighbours don't need
+    // range checks.
+    pub fn get_offset(&self, x : usize, y : usize, dx : i32, dy : i32) -> Option<&T> {
+        self.index_for_signed_location(x as i64 + i64::from(dx), y as i64 + i64::from(dy)).map(|index| &self.grid[index])
     }
 
-    pub fn iter(&self) -> std::slice::Iter<T> {
+    pub fn get_offset_mut(&mut self, x : usize, y : usize, dx : i32, dy : i32) -> Option<&mut T> {
+        self.index_for_signed_location(x as i64 + i64::from(dx), y as i64 + i64::from(dy)).map(move |index| &mut self.grid[index])
+    }
+
+    pub fn get_point(&self, point : Point) -> Option<&T> {
+        self.index_for_signed_location(i64::from(point.x), i64::from(point.y)).map(|index| &self.grid[index])
+    }
+
+    pub fn get_point_mut(&mut self, point : Point) -> Option<&mut T> {
+        self.index_for_signed_location(i64::from(point.x), i64::from(point.y)).map(move |index| &mut self.grid[index])
+    }
+
+    // Treats the grid as a torus, so any coordinates land somewhere. None only for an empty grid.
+    pub fn get_wrapped(&self, x : i64, y : i64) -> Option<&T> {
+        self.index_for_wrapped_location(x, y).map(|index| &self.grid[index])
+    }
+
+    pub fn get_wrapped_mut(&mut self, x : i64, y : i64) -> Option<&mut T> {
+        self.index_for_wrapped_location(x, y).map(move |index| &mut self.grid[index])
+    }
+
+    pub fn get_offset_wrapped(&self, x : usize, y : usize, dx : i32, dy : i32) -> Option<&T> {
+        self.get_wrapped(x as i64 + i64::from(dx), y as i64 + i64::from(dy))
+    }
+
+    pub fn get_offset_wrapped_mut(&mut self, x : usize, y : usize, dx : i32, dy : i32) -> Option<&mut T> {
+        self.get_wrapped_mut(x as i64 + i64::from(dx), y as i64 + i64::from(dy))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
         self.grid.iter()
     }
 
-    pub fn rows(&self) -> std::slice::Chunks<T> {
+    pub fn rows(&self) -> std::slice::Chunks<'_, T> {
         self.grid.chunks(self.size_x())
     }
 
-    pub fn enumerate(&self) -> GridIterator<T> {
+    pub fn enumerate(&self) -> GridIterator<'_, T> {
         GridIterator {
             grid : self,
             iter : self.grid.iter().enumerate(),
         }
     }
 
+    // A grid of the same size, with each value converted.
+    pub fn map<U, F>(&self, f : F) -> Grid<U>
+    where F : FnMut(&T) -> U {
+        Grid {
+            grid : self.grid.iter().map(f).collect(),
+            size_x : self.size_x,
+        }
+    }
+
+    // Skips neighbours that would be off the edge.
+    pub fn neighbours<'t>(&'t self, x : usize, y : usize, stencil : Stencil<'t>) -> Neighbours<'t, T> {
+        Neighbours {
+            grid : self,
+            x,
+            y,
+            offsets : stencil.offsets(y),
+            wrap : false,
+            pos : 0,
+        }
+    }
+
+    // Wraps around the edges instead, so every neighbour is there. On grids smaller than the
+    // stencil, the same cell can show up more than once, including the location itself.
+    pub fn neighbours_wrapped<'t>(&'t self, x : usize, y : usize, stencil : Stencil<'t>) -> Neighbours<'t, T> {
+        Neighbours {
+            wrap : true,
+            ..self.neighbours(x, y, stencil)
+        }
+    }
+
     pub fn rotate_right(&self) -> Grid<T>
     where T : Clone {
         let mut output = Grid::new();
@@ -155,6 +392,178 @@ impl<T> Grid<T> {
     }
 }
 
+impl BoundingBox {
+    pub fn new(x : i32, y : i32) -> BoundingBox {
+        BoundingBox {
+            min_x : x,
+            min_y : y,
+            max_x : x,
+            max_y : y,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max_x - self.min_x) as usize + 1
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max_y - self.min_y) as usize + 1
+    }
+
+    pub fn contains(&self, x : i32, y : i32) -> bool {
+        x >= self.min_x && x <= self.max_x && y >= self.min_y && y <= self.max_y
+    }
+
+    // Grows the box to hold the point.
+    pub fn include(&mut self, x : i32, y : i32) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    // Every point inside, row by row.
+    pub fn points(&self) -> impl Iterator<Item = (i32, i32)> {
+        let (min_x, max_x) = (self.min_x, self.max_x);
+        (self.min_y ..= self.max_y).flat_map(move |y| (min_x ..= max_x).map(move |x| (x, y)))
+    }
+}
+
+const ORTHOGONAL_OFFSETS : [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const DIAGONAL_OFFSETS : [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+const ALL_OFFSETS : [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+const HEX_EVEN_ROW_OFFSETS : [(i32, i32); 6] = [(-1, -1), (0, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)];
+const HEX_ODD_ROW_OFFSETS : [(i32, i32); 6] = [(0, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (1, 1)];
+
+impl<'s> Stencil<'s> {
+    // All of them in reading order, except custom ones, which keep their own order. Hex neighbours
+    // depend on which row they're around.
+    pub fn offsets(&self, y : usize) -> &'s [(i32, i32)] {
+        match *self {
+            Stencil::Orthogonal => &ORTHOGONAL_OFFSETS,
+            Stencil::Diagonal => &DIAGONAL_OFFSETS,
+            Stencil::All => &ALL_OFFSETS,
+            Stencil::HexOffset if y.is_multiple_of(2) => &HEX_EVEN_ROW_OFFSETS,
+            Stencil::HexOffset => &HEX_ODD_ROW_OFFSETS,
+            Stencil::Custom(offsets) => offsets,
+        }
+    }
+}
+
+impl<'t, T> Iterator for Neighbours<'t, T>
+where T : 't {
+    type Item = Neighbour<'t, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&(dx, dy)) = self.offsets.get(self.pos) {
+            self.pos += 1;
+
+            let (x, y) = (self.x as i64 + i64::from(dx), self.y as i64 + i64::from(dy));
+            let index = if self.wrap {
+                self.grid.index_for_wrapped_location(x, y)
+            } else {
+                self.grid.index_for_signed_location(x, y)
+            };
+
+            if let Some(index) = index {
+                return Some(Neighbour {
+                    location : self.grid.location_for_index(index),
+                    direction : Direction::from_grid_offset((dx, dy)),
+                    value : &self.grid.grid[index],
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl<T> Plane<T> for Grid<T> {
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        if self.grid.is_empty() {
+            None
+        } else {
+            Some(BoundingBox {
+                min_x : 0,
+                min_y : 0,
+                max_x : self.size_x() as i32 - 1,
+                max_y : self.size_y() as i32 - 1,
+            })
+        }
+    }
+
+    fn at(&self, x : i32, y : i32) -> Option<&T> {
+        self.get_point(Point::new(x, y))
+    }
+
+    fn at_mut(&mut self, x : i32, y : i32) -> Option<&mut T> {
+        self.get_point_mut(Point::new(x, y))
+    }
+}
+
+// Indexing panics off the edge, like a Vec. Use get or get_point to check instead.
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y) : (usize, usize)) -> &T {
+        match self.index_for_location(x, y) {
+            Some(index) => &self.grid[index],
+            None => panic!("{}, {} is outside a {}x{} grid", x, y, self.size_x(), self.size_y()),
+        }
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y) : (usize, usize)) -> &mut T {
+        match self.index_for_location(x, y) {
+            Some(index) => &mut self.grid[index],
+            None => panic!("{}, {} is outside a {}x{} grid", x, y, self.size_x(), self.size_y()),
+        }
+    }
+}
+
+impl<T> Index<Point> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, point : Point) -> &T {
+        match self.index_for_signed_location(i64::from(point.x), i64::from(point.y)) {
+            Some(index) => &self.grid[index],
+            None => panic!("{} is outside a {}x{} grid", point, self.size_x(), self.size_y()),
+        }
+    }
+}
+
+impl<T> IndexMut<Point> for Grid<T> {
+    fn index_mut(&mut self, point : Point) -> &mut T {
+        match self.index_for_signed_location(i64::from(point.x), i64::from(point.y)) {
+            Some(index) => &mut self.grid[index],
+            None => panic!("{} is outside a {}x{} grid", point, self.size_x(), self.size_y()),
+        }
+    }
+}
+
+fn numbered_lines(input : &str) -> Vec<(usize, &str)> {
+    input.lines().enumerate().map(|(index, line)| (index + 1, line)).collect()
+}
+
+fn trim_blank_lines(mut lines : Vec<(usize, &str)>) -> Vec<(usize, &str)> {
+    while lines.last().is_some_and(|&(_, line)| line.is_empty()) {
+        lines.pop();
+    }
+
+    let first = lines.iter().position(|&(_, line)| !line.is_empty()).unwrap_or(lines.len());
+    lines.split_off(first)
+}
+
+impl fmt::Display for GridParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GridParseError::BadChar { line, column, ch } => write!(f, "line {}, column {}: unexpected `{}`", line, column, ch),
+            GridParseError::RaggedLine { line, len, expected } => write!(f, "line {}: {} chars long, expected {}", line, len, expected),
+        }
+    }
+}
+
 impl<T> fmt::Display for Grid<T>
 where T : fmt::Display {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -205,7 +614,7 @@ mod test {
             grid.add_row(vec![row, row + 1, row + 2, row + 3, row + 4]);
         }
 
-        assert_eq!(grid.iter().map(|v| *v).collect::<Vec<u32>>(), vec![0, 1, 2, 3, 4, 1, 2, 3, 4, 5]);
+        assert_eq!(grid.iter().copied().collect::<Vec<u32>>(), vec![0, 1, 2, 3, 4, 1, 2, 3, 4, 5]);
     }
 
     #[test]
@@ -265,7 +674,7 @@ mod test {
                 vec![true, false],
                 vec![false, false],]);
 
-        assert_eq!(grid.matches_on(&grid.clone(), 0, 0), true);
+        assert!(grid.matches_on(&grid.clone(), 0, 0));
     }
 
     #[test]
@@ -286,7 +695,7 @@ mod test {
                 vec![false, false, false],
                 vec![false, true, false],]);
 
-        assert_eq!(grid.matches_on(&other, 0, 0), true);
+        assert!(grid.matches_on(&other, 0, 0));
     }
 
     #[test]
@@ -307,7 +716,7 @@ mod test {
                 vec![false, false, false],
                 vec![false, true, false],]);
 
-        assert_eq!(grid.matches_on(&other, 1, 1), true);
+        assert!(grid.matches_on(&other, 1, 1));
     }
 
     #[test]
@@ -322,7 +731,7 @@ mod test {
         // .#.
         let other = grid.rotate_right();
 
-        assert_eq!(grid.matches_on(&other, 0, 0), false);
+        assert!(!grid.matches_on(&other, 0, 0));
     }
 
     #[test]
@@ -343,7 +752,7 @@ mod test {
                 vec![false, false, false],
                 vec![false, true, false],]);
 
-        assert_eq!(grid.matches_on(&other, 0, 1), false);
+        assert!(!grid.matches_on(&other, 0, 1));
     }
 
     #[test]
@@ -365,7 +774,7 @@ mod test {
                 vec![false, true, false],]);
 
         grid.stamp_onto(&mut other, 0, 0);
-        assert_eq!(grid.matches_on(&other, 0, 0), true);
+        assert!(grid.matches_on(&other, 0, 0));
     }
 
     #[test]
@@ -396,7 +805,7 @@ mod test {
                 vec![false, false, false],
                 vec![false, true, false],]);
 
-        assert_eq!(grid, Grid::from_rows(grid.rows().map(|a| a.iter().cloned().collect()).collect()));
+        assert_eq!(grid, Grid::from_rows(grid.rows().map(|a| a.to_vec()).collect()));
     }
 
     #[test]
@@ -413,4 +822,147 @@ mod test {
 
         assert_eq!(grid, other);
     }
+
+    #[test]
+    fn parse() {
+        let grid = Grid::<char>::parse("\nab\ncd\n\n").unwrap();
+        assert_eq!(grid, Grid::from_rows(vec![vec!['a', 'b'], vec!['c', 'd']]));
+
+        let grid = Grid::parse_with("12\n34", |ch| ch.to_digit(10)).unwrap();
+        assert_eq!(grid, Grid::from_rows(vec![vec![1, 2], vec![3, 4]]));
+
+        assert_eq!(Grid::<bool>::parse_with("..\n.#", |ch| Some(ch == '#')).unwrap().get(1, 1), Some(&true));
+        assert_eq!(Grid::<char>::parse("").unwrap(), Grid::new());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Grid::parse_with("12\n3x", |ch| ch.to_digit(10)), Err(GridParseError::BadChar { line : 2, column : 2, ch : 'x' }));
+        assert_eq!(Grid::<char>::parse("abc\nde"), Err(GridParseError::RaggedLine { line : 2, len : 2, expected : 3 }));
+
+        // Line numbers count the leading blank lines that were skipped.
+        let error = Grid::<u8>::parse("\n\n12\n3\u{3bb}").unwrap_err();
+        assert_eq!(format!("{}", error), "line 4, column 2: unexpected `\u{3bb}`");
+    }
+
+    #[test]
+    fn parse_padded() {
+        let grid = Grid::<char>::parse_padded("  |\n  +-\n", ' ').unwrap();
+        assert_eq!(grid, Grid::from_rows(vec![vec![' ', ' ', '|', ' '], vec![' ', ' ', '+', '-']]));
+
+        let grid = Grid::parse_padded_with("1\n\n22", 0, |ch| ch.to_digit(10)).unwrap();
+        assert_eq!(grid, Grid::from_rows(vec![vec![1, 0], vec![0, 0], vec![2, 2]]));
+    }
+
+    #[test]
+    fn parse_all() {
+        let grids = Grid::<char>::parse_all("\nab\ncd\n\n\nefg\n\nh\n").unwrap();
+        assert_eq!(grids, vec![
+            Grid::from_rows(vec![vec!['a', 'b'], vec!['c', 'd']]),
+            Grid::from_rows(vec![vec!['e', 'f', 'g']]),
+            Grid::from_rows(vec![vec!['h']]),]);
+
+        assert_eq!(Grid::<char>::parse_all("ab\n\ncd\ne"), Err(GridParseError::RaggedLine { line : 4, len : 1, expected : 2 }));
+    }
+
+    #[test]
+    fn get_out_of_range() {
+        let mut grid = Grid::<char>::parse("ab\ncd").unwrap();
+        assert_eq!(grid.get(1, 1), Some(&'d'));
+        assert_eq!(grid.get(2, 0), None);
+        assert_eq!(grid.get(0, 2), None);
+        assert_eq!(grid.get_mut(2, 1), None);
+        assert_eq!(Grid::<char>::new().get(0, 0), None);
+        assert_eq!(Grid::<char>::new().size_y(), 0);
+    }
+
+    #[test]
+    fn get_offset() {
+        let mut grid = Grid::<char>::parse("abc\ndef").unwrap();
+        assert_eq!(grid.get_offset(1, 1, -1, -1), Some(&'a'));
+        assert_eq!(grid.get_offset(0, 0, -1, 0), None);
+        assert_eq!(grid.get_offset(2, 1, 1, 0), None);
+        assert_eq!(grid.get_offset(2, 1, 0, 1), None);
+
+        *grid.get_offset_mut(0, 0, 2, 1).unwrap() = 'F';
+        assert_eq!(grid.get(2, 1), Some(&'F'));
+    }
+
+    #[test]
+    fn get_wrapped() {
+        let mut grid = Grid::<char>::parse("abc\ndef").unwrap();
+        assert_eq!(grid.get_wrapped(-1, -1), Some(&'f'));
+        assert_eq!(grid.get_wrapped(3, 2), Some(&'a'));
+        assert_eq!(grid.get_wrapped(-7, 5), Some(&'f'));
+        assert_eq!(grid.get_offset_wrapped(0, 0, -1, 0), Some(&'c'));
+
+        *grid.get_offset_wrapped_mut(2, 1, 1, 1).unwrap() = 'A';
+        assert_eq!(grid.get(0, 0), Some(&'A'));
+        assert_eq!(Grid::<char>::new().get_wrapped(0, 0), None);
+    }
+
+    #[test]
+    fn index() {
+        let mut grid = Grid::<char>::parse("ab\ncd").unwrap();
+        assert_eq!(grid[(1, 0)], 'b');
+        assert_eq!(grid[Point::new(0, 1)], 'c');
+        assert_eq!(grid.get_point(Point::new(-1, 0)), None);
+
+        grid[(0, 0)] = 'A';
+        grid[Point::new(1, 0) + Point::new(0, 1)] = 'D';
+        assert_eq!(grid, Grid::parse("Ab\ncD").unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "(-1, 0) is outside a 2x2 grid")]
+    fn index_out_of_range() {
+        let grid = Grid::<char>::parse("ab\ncd").unwrap();
+        let _ = grid[Point::new(-1, 0)];
+    }
+
+    #[test]
+    fn map() {
+        let grid = Grid::<char>::parse("12\n34").unwrap();
+        assert_eq!(grid.map(|ch| ch.to_digit(10).unwrap() * 2), Grid::from_rows(vec![vec![2, 4], vec![6, 8]]));
+    }
+
+    #[test]
+    fn neighbours() {
+        // abc
+        // def
+        // ghi
+        let grid = Grid::<char>::parse("abc\ndef\nghi").unwrap();
+        let values = |stencil| grid.neighbours(1, 1, stencil).map(|n| *n.value).collect::<String>();
+        assert_eq!(values(Stencil::Orthogonal), "bdfh");
+        assert_eq!(values(Stencil::Diagonal), "acgi");
+        assert_eq!(values(Stencil::All), "abcdfghi");
+        assert_eq!(values(Stencil::Custom(&[(0, 0), (1, -1), (2, 0)])), "ec");
+
+        let corner = grid.neighbours(0, 0, Stencil::All).map(|n| (n.location, n.direction, *n.value)).collect::<Vec<_>>();
+        assert_eq!(corner, vec![((1, 0), Some(Direction::Right), 'b'), ((0, 1), Some(Direction::Down), 'd'), ((1, 1), None, 'e')]);
+    }
+
+    #[test]
+    fn neighbours_wrapped() {
+        let grid = Grid::<char>::parse("abc\ndef\nghi").unwrap();
+        let corner = grid.neighbours_wrapped(0, 0, Stencil::Orthogonal).map(|n| (n.location, n.direction, *n.value)).collect::<Vec<_>>();
+        assert_eq!(corner, vec![
+            ((0, 2), Some(Direction::Up), 'g'),
+            ((2, 0), Some(Direction::Left), 'c'),
+            ((1, 0), Some(Direction::Right), 'b'),
+            ((0, 1), Some(Direction::Down), 'd'),]);
+
+        assert_eq!(grid.neighbours_wrapped(2, 2, Stencil::All).map(|n| *n.value).collect::<String>(), "efdhgbca");
+    }
+
+    #[test]
+    fn neighbours_hex() {
+        // Odd rows are shifted right:
+        // a b c
+        //  d e f
+        // g h i
+        let grid = Grid::<char>::parse("abc\ndef\nghi").unwrap();
+        assert_eq!(grid.neighbours(1, 1, Stencil::HexOffset).map(|n| *n.value).collect::<String>(), "bcdfhi");
+        assert_eq!(grid.neighbours(1, 2, Stencil::HexOffset).map(|n| *n.value).collect::<String>(), "degi");
+    }
 }
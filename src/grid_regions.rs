@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+use grid::*;
+
+// Measurements of one connected region. Perimeter and sides count the orthogonal edges between the
+// region and everything else, including the edge of the grid, even if the region was connected
+// diagonally.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Region {
+    pub label : usize,
+
+    // The region's first cell in reading order.
+    pub seed : (usize, usize),
+
+    pub area : usize,
+    pub perimeter : usize,
+
+    // Straight runs of edge, so a plain rectangle has 4 no matter its size.
+    pub sides : usize,
+
+    pub bounds : BoundingBox,
+    pub touches_border : bool,
+}
+
+// Every cell labelled with the region it's in. Labels count up from 0 in reading order of the
+// regions' seeds, and index into regions.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Components {
+    labels : Grid<usize>,
+    regions : Vec<Region>,
+}
+
+impl Components {
+    pub fn labels(&self) -> &Grid<usize> {
+        &self.labels
+    }
+
+    pub fn into_labels(self) -> Grid<usize> {
+        self.labels
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn region_at(&self, x : usize, y : usize) -> Option<&Region> {
+        self.labels.get(x, y).map(|&label| &self.regions[label])
+    }
+}
+
+impl<T> Grid<T> {
+    // Every cell reachable from the seed, in the order they were found. connected is given the
+    // values of two neighbouring cells, and says whether the fill can move from the first to the
+    // second.
+    pub fn flood_fill<C>(&self, x : usize, y : usize, stencil : Stencil<'_>, mut connected : C) -> Vec<(usize, usize)>
+    where C : FnMut(&T, &T) -> bool {
+        let mut seen = self.map(|_| false);
+        self.fill_from(x, y, stencil, &mut connected, &mut seen)
+    }
+
+    fn fill_from<C>(&self, x : usize, y : usize, stencil : Stencil<'_>, connected : &mut C, seen : &mut Grid<bool>) -> Vec<(usize, usize)>
+    where C : FnMut(&T, &T) -> bool {
+        let mut filled = vec![];
+        if self.get(x, y).is_none() {
+            return filled;
+        }
+
+        seen[(x, y)] = true;
+        let mut queue = VecDeque::new();
+        queue.push_back((x, y));
+        while let Some((x, y)) = queue.pop_front() {
+            filled.push((x, y));
+            let value = &self[(x, y)];
+            for neighbour in self.neighbours(x, y, stencil) {
+                if !seen[neighbour.location] && connected(value, neighbour.value) {
+                    seen[neighbour.location] = true;
+                    queue.push_back(neighbour.location);
+                }
+            }
+        }
+
+        filled
+    }
+
+    // Splits the whole grid into regions. connected should be symmetric, like comparing values for
+    // equality; to only count some kinds of cell, filter the regions by the value at their seed.
+    pub fn components<C>(&self, stencil : Stencil<'_>, mut connected : C) -> Components
+    where C : FnMut(&T, &T) -> bool {
+        let mut seen = self.map(|_| false);
+        let mut labels = self.map(|_| 0);
+        let mut regions = vec![];
+        let mut region_cells = vec![];
+
+        for y in 0 .. self.size_y() {
+            for x in 0 .. self.size_x() {
+                if seen[(x, y)] {
+                    continue;
+                }
+
+                let cells = self.fill_from(x, y, stencil, &mut connected, &mut seen);
+                for &location in &cells {
+                    labels[location] = regions.len();
+                }
+
+                regions.push(self.measure(regions.len(), &cells));
+                region_cells.push(cells);
+            }
+        }
+
+        // Edges need every label in place first.
+        for (region, cells) in regions.iter_mut().zip(region_cells) {
+            let (perimeter, sides) = fences(&labels, region.label, &cells);
+            region.perimeter = perimeter;
+            region.sides = sides;
+        }
+
+        Components {
+            labels,
+            regions,
+        }
+    }
+
+    fn measure(&self, label : usize, cells : &[(usize, usize)]) -> Region {
+        let seed = *cells.iter().min_by_key(|&&(x, y)| (y, x)).unwrap();
+        let mut bounds = BoundingBox::new(seed.0 as i32, seed.1 as i32);
+        for &(x, y) in cells {
+            bounds.include(x as i32, y as i32);
+        }
+
+        Region {
+            label,
+            seed,
+            area : cells.len(),
+            perimeter : 0,
+            sides : 0,
+            bounds,
+            touches_border : bounds.min_x == 0 || bounds.min_y == 0 || bounds.max_x as usize == self.size_x() - 1 || bounds.max_y as usize == self.size_y() - 1,
+        }
+    }
+}
+
+// The perimeter and number of sides of the cells with a label. Each corner of the outline starts a
+// new side, so sides are counted as corners.
+fn fences(labels : &Grid<usize>, label : usize, cells : &[(usize, usize)]) -> (usize, usize) {
+    let inside = |x : usize, y : usize, dx : i32, dy : i32| labels.get_offset(x, y, dx, dy) == Some(&label);
+    let mut perimeter = 0;
+    let mut sides = 0;
+    for &(x, y) in cells {
+        perimeter += [(0, -1), (-1, 0), (1, 0), (0, 1)].iter().filter(|&&(dx, dy)| !inside(x, y, dx, dy)).count();
+
+        for &(dx, dy) in &[(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            let (across, down, diagonal) = (inside(x, y, dx, 0), inside(x, y, 0, dy), inside(x, y, dx, dy));
+            if (!across && !down) || (across && down && !diagonal) {
+                sides += 1;
+            }
+        }
+    }
+
+    (perimeter, sides)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flood_fill() {
+        let grid = Grid::<char>::parse("#.#\n##.\n..#").unwrap();
+        let same = |a : &char, b : &char| a == b;
+        assert_eq!(grid.flood_fill(0, 0, Stencil::Orthogonal, same), vec![(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(grid.flood_fill(0, 0, Stencil::All, same), vec![(0, 0), (0, 1), (1, 1), (2, 0), (2, 2)]);
+        assert_eq!(grid.flood_fill(5, 0, Stencil::All, same), vec![]);
+    }
+
+    #[test]
+    fn garden_plots() {
+        // 2024 day 12's first example.
+        let grid = Grid::<char>::parse(
+r"AAAA
+BBCD
+BBCC
+EEEC").unwrap();
+        let components = grid.components(Stencil::Orthogonal, |a, b| a == b);
+        let summary = components.regions().iter().map(|r| (grid[r.seed], r.area, r.perimeter, r.sides)).collect::<Vec<_>>();
+        assert_eq!(summary, vec![('A', 4, 10, 4), ('B', 4, 8, 4), ('C', 4, 10, 8), ('D', 1, 4, 4), ('E', 3, 8, 4)]);
+        assert_eq!(format!("{}", components.labels()), "0000\n1123\n1122\n4442\n");
+        assert_eq!(components.region_at(3, 3).unwrap().bounds, BoundingBox { min_x : 2, min_y : 1, max_x : 3, max_y : 3 });
+    }
+
+    #[test]
+    fn holes() {
+        let grid = Grid::<char>::parse(
+r"#####
+#.#.#
+#####").unwrap();
+        let components = grid.components(Stencil::Orthogonal, |a, b| a == b);
+        assert_eq!(components.regions().len(), 3);
+
+        let outer = &components.regions()[0];
+        assert_eq!((outer.area, outer.perimeter, outer.sides), (13, 24, 12));
+        assert!(outer.touches_border);
+
+        let hole = components.region_at(3, 1).unwrap();
+        assert_eq!((hole.area, hole.perimeter, hole.sides), (1, 4, 4));
+        assert!(!hole.touches_border);
+    }
+
+    #[test]
+    fn diagonal_connectivity() {
+        let grid = Grid::<char>::parse("#.\n.#").unwrap();
+        assert_eq!(grid.components(Stencil::Orthogonal, |a, b| a == b).regions().len(), 4);
+
+        let components = grid.components(Stencil::All, |a, b| a == b);
+        assert_eq!(format!("{}", components.labels()), "01\n10\n");
+
+        // Still measured with orthogonal edges, so the touching corners count as two.
+        let region = &components.regions()[0];
+        assert_eq!((region.area, region.perimeter, region.sides), (2, 8, 8));
+    }
+}
@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use grid::*;
+use direction::Direction;
+
+// A route through a grid, moving one orthogonal step at a time.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GridPath {
+    // Every cell visited, from the source to the destination inclusive.
+    pub locations : Vec<(usize, usize)>,
+
+    // One fewer than the locations.
+    pub steps : Vec<Direction>,
+
+    pub cost : u64,
+}
+
+// What a search found. Cells it never reached, or didn't get to before stopping at a goal, have no
+// distance.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GridSearch {
+    distances : Grid<Option<u64>>,
+    previous : Grid<Option<(usize, usize)>>,
+    goal : Option<(usize, usize)>,
+}
+
+impl GridSearch {
+    fn new<T>(grid : &Grid<T>) -> GridSearch {
+        GridSearch {
+            distances : grid.map(|_| None),
+            previous : grid.map(|_| None),
+            goal : None,
+        }
+    }
+
+    pub fn distances(&self) -> &Grid<Option<u64>> {
+        &self.distances
+    }
+
+    pub fn into_distances(self) -> Grid<Option<u64>> {
+        self.distances
+    }
+
+    pub fn distance(&self, x : usize, y : usize) -> Option<u64> {
+        self.distances.get(x, y).cloned().unwrap_or(None)
+    }
+
+    // The first goal the search reached, which is a closest one.
+    pub fn goal(&self) -> Option<(usize, usize)> {
+        self.goal
+    }
+
+    // The shortest path from any of the sources to the goal.
+    pub fn path(&self) -> Option<GridPath> {
+        self.goal.and_then(|(x, y)| self.path_to(x, y))
+    }
+
+    pub fn path_to(&self, x : usize, y : usize) -> Option<GridPath> {
+        let cost = self.distance(x, y)?;
+
+        let mut locations = vec![(x, y)];
+        while let Some(previous) = self.previous[*locations.last().unwrap()] {
+            locations.push(previous);
+        }
+        locations.reverse();
+
+        let steps = locations.windows(2).map(|pair| {
+            let offset = (pair[1].0 as i32 - pair[0].0 as i32, pair[1].1 as i32 - pair[0].1 as i32);
+            Direction::from_grid_offset(offset).unwrap()
+        }).collect();
+
+        Some(GridPath {
+            locations,
+            steps,
+            cost,
+        })
+    }
+}
+
+impl<T> Grid<T> {
+    // Fewest steps from any of the sources. passable is given the values being moved from and to.
+    // Stops at the first goal reached; pass a goal that never matches to fill in every distance.
+    pub fn bfs<P, G>(&self, sources : &[(usize, usize)], mut passable : P, mut is_goal : G) -> GridSearch
+    where P : FnMut(&T, &T) -> bool,
+          G : FnMut((usize, usize), &T) -> bool {
+        let mut search = GridSearch::new(self);
+        let mut queue = VecDeque::new();
+        for &source in sources {
+            if self.get(source.0, source.1).is_some() && search.distances[source].is_none() {
+                search.distances[source] = Some(0);
+                queue.push_back(source);
+            }
+        }
+
+        while let Some((x, y)) = queue.pop_front() {
+            let value = &self[(x, y)];
+            if is_goal((x, y), value) {
+                search.goal = Some((x, y));
+                break;
+            }
+
+            let distance = search.distances[(x, y)].unwrap();
+            for neighbour in self.neighbours(x, y, Stencil::Orthogonal) {
+                if search.distances[neighbour.location].is_none() && passable(value, neighbour.value) {
+                    search.distances[neighbour.location] = Some(distance + 1);
+                    search.previous[neighbour.location] = Some((x, y));
+                    queue.push_back(neighbour.location);
+                }
+            }
+        }
+
+        search
+    }
+
+    // Lowest total cost from any of the sources. cost is given the values being moved from and to,
+    // and returns None if the move isn't allowed.
+    pub fn dijkstra<C, G>(&self, sources : &[(usize, usize)], cost : C, is_goal : G) -> GridSearch
+    where C : FnMut(&T, &T) -> Option<u64>,
+          G : FnMut((usize, usize), &T) -> bool {
+        self.astar(sources, cost, |_| 0, is_goal)
+    }
+
+    // Like dijkstra, but guided by a heuristic that must never overestimate the remaining cost to a
+    // goal. Distances are only filled in for the cells it had to look at.
+    pub fn astar<C, H, G>(&self, sources : &[(usize, usize)], mut cost : C, mut heuristic : H, mut is_goal : G) -> GridSearch
+    where C : FnMut(&T, &T) -> Option<u64>,
+          H : FnMut((usize, usize)) -> u64,
+          G : FnMut((usize, usize), &T) -> bool {
+        let mut search = GridSearch::new(self);
+        let mut open = BinaryHeap::new();
+        for &source in sources {
+            if self.get(source.0, source.1).is_some() && search.distances[source].is_none() {
+                search.distances[source] = Some(0);
+                open.push(Reverse((heuristic(source), 0, source)));
+            }
+        }
+
+        while let Some(Reverse((_, distance, (x, y)))) = open.pop() {
+            // A cheaper way here was already found after this entry was queued.
+            if search.distances[(x, y)] != Some(distance) {
+                continue;
+            }
+
+            let value = &self[(x, y)];
+            if is_goal((x, y), value) {
+                search.goal = Some((x, y));
+                break;
+            }
+
+            for neighbour in self.neighbours(x, y, Stencil::Orthogonal) {
+                if let Some(step) = cost(value, neighbour.value) {
+                    let next = distance + step;
+                    if search.distances[neighbour.location].is_none_or(|d| next < d) {
+                        search.distances[neighbour.location] = Some(next);
+                        search.previous[neighbour.location] = Some((x, y));
+                        open.push(Reverse((next + heuristic(neighbour.location), next, neighbour.location)));
+                    }
+                }
+            }
+        }
+
+        search
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAZE : &str =
+r"#########
+#S..#...#
+#.#.#.#.#
+#.#...#E#
+#########";
+
+    #[test]
+    fn bfs() {
+        let grid = Grid::<char>::parse(MAZE).unwrap();
+        let search = grid.bfs(&[(1, 1)], |_, &to| to != '#', |_, &ch| ch == 'E');
+        assert_eq!(search.goal(), Some((7, 3)));
+
+        let path = search.path().unwrap();
+        assert_eq!(path.cost, 12);
+        assert_eq!(path.locations.len(), 13);
+        assert_eq!(path.locations.first(), Some(&(1, 1)));
+        assert_eq!(path.steps.iter().map(|d| format!("{}", d)).collect::<String>(), "RRDDRRUURRDD");
+    }
+
+    #[test]
+    fn distance_map() {
+        let grid = Grid::<char>::parse(MAZE).unwrap();
+        let search = grid.bfs(&[(1, 1)], |_, &to| to != '#', |_, _| false);
+        assert_eq!(search.goal(), None);
+        assert_eq!(search.path(), None);
+        assert_eq!(search.distance(1, 3), Some(2));
+        assert_eq!(search.distance(0, 0), None);
+        assert_eq!(search.path_to(1, 3).unwrap().steps, vec![Direction::Down, Direction::Down]);
+
+        let rendered = search.distances().map(|d| d.map_or('#', |d| std::char::from_digit(d as u32 % 10, 10).unwrap()));
+        assert_eq!(format!("{}", rendered), "#########\n#012#890#\n#1#3#7#1#\n#2#456#2#\n#########\n");
+    }
+
+    #[test]
+    fn multiple_sources() {
+        let grid = Grid::<char>::parse(MAZE).unwrap();
+        let search = grid.bfs(&[(1, 1), (7, 3)], |_, &to| to != '#', |_, _| false);
+        assert_eq!(search.distance(4, 3), Some(5));
+        assert_eq!(search.distance(6, 1), Some(3));
+        assert_eq!(search.distance(7, 1), Some(2));
+        assert_eq!(search.path_to(7, 1).unwrap().locations, vec![(7, 3), (7, 2), (7, 1)]);
+    }
+
+    #[test]
+    fn weighted() {
+        // The direct route along the top costs more than going around.
+        let grid = Grid::parse_with("1991\n1111", |ch| ch.to_digit(10).map(u64::from)).unwrap();
+        let cost = |_ : &u64, &to : &u64| Some(to);
+
+        let search = grid.dijkstra(&[(0, 0)], cost, |location, _| location == (3, 0));
+        let path = search.path().unwrap();
+        assert_eq!(path.cost, 5);
+        assert_eq!(path.steps, vec![Direction::Down, Direction::Right, Direction::Right, Direction::Right, Direction::Up]);
+
+        let manhattan = |(x, y) : (usize, usize)| (3 - x + y) as u64;
+        let search = grid.astar(&[(0, 0)], cost, manhattan, |location, _| location == (3, 0));
+        assert_eq!(search.path().unwrap().cost, 5);
+
+        // Only climbing by at most one.
+        let grid = Grid::parse_with("12\n43", |ch| ch.to_digit(10)).unwrap();
+        let search = grid.dijkstra(&[(0, 0)], |&from, &to| if to <= from + 1 { Some(1) } else { None }, |_, _| false);
+        assert_eq!(search.distance(0, 1), Some(3));
+        assert_eq!(search.path_to(0, 1).unwrap().locations, vec![(0, 0), (1, 0), (1, 1), (0, 1)]);
+        assert_eq!(grid.bfs(&[(0, 0)], |&from, &to| to < from, |_, _| false).distance(0, 1), None);
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::VecDeque;
+use std::fmt;
+use aocisa::MachineStatus;
+use aocisa::ParseError;
+use aocisa::Process;
+use aocisa::Scheduler;
+use aocisa::SchedulerOutcome;
+
+const VALUE_FORM : &str = "<value>";
+
+// The most memory a Vm will grow to, in values. Writing past it is a fault, rather than an attempt
+// to allocate however much a stray address asks for.
+pub const MEMORY_LIMIT : usize = 1 << 24;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+// Why a Vm can't run the instruction at its ip.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Fault {
+    InvalidOpcode(i64),
+    InvalidMode(i64),
+
+    // An instruction tried to write to an immediate mode parameter.
+    ImmediateWrite,
+
+    // An instruction used, or jumped to, this address.
+    NegativeAddress(i64),
+
+    // An instruction tried to write to this address, which is past MEMORY_LIMIT.
+    AddressTooLarge(usize),
+
+    // A result didn't fit in an i64.
+    Overflow,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Ready,
+    Blocked,
+    Halted,
+
+    // The instruction at the ip can't run. The vm won't advance, but fixing up its memory lets it
+    // carry on.
+    Faulted(usize, Fault),
+}
+
+// What the instruction at the ip will do, worked out before anything changes so that a fault
+// leaves the vm untouched.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Write(usize, i64),
+    Input(usize),
+    Output(i64),
+    Jump(usize),
+    AdjustBase(i64),
+    Halt,
+}
+
+// An Intcode computer from 2019. Memory grows as needed, and reads past the end return 0. Input
+// and output go through queues, like aocisa::Machine, so a Vm pauses when it needs input that
+// hasn't arrived yet and can be resumed once it has.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Vm {
+    memory : Vec<i64>,
+    ip : usize,
+    relative_base : i64,
+    inbox : VecDeque<i64>,
+    outbox : VecDeque<i64>,
+    send_count : usize,
+    last_sent : Option<i64>,
+    steps : u64,
+}
+
+pub fn parse_memory(input : &str) -> Result<Vec<i64>, ParseError> {
+    let mut column = 1;
+    let mut memory = vec![];
+    for token in input.trim().split(',') {
+        let trimmed = token.trim();
+        let token_column = column + (token.len() - token.trim_start().len());
+        match trimmed.parse::<i64>() {
+            Ok(value) => memory.push(value),
+            Err(_) => return Err(ParseError::new(token_column, trimmed, &[VALUE_FORM])),
+        }
+
+        column += token.chars().count() + 1;
+    }
+
+    Ok(memory)
+}
+
+impl Mode {
+    fn from_digit(digit : i64) -> Result<Mode, Fault> {
+        match digit {
+            0 => Ok(Mode::Position),
+            1 => Ok(Mode::Immediate),
+            2 => Ok(Mode::Relative),
+            _ => Err(Fault::InvalidMode(digit)),
+        }
+    }
+}
+
+impl Vm {
+    pub fn new(memory : Vec<i64>) -> Vm {
+        Vm {
+            memory,
+            ip : 0,
+            relative_base : 0,
+            inbox : VecDeque::new(),
+            outbox : VecDeque::new(),
+            send_count : 0,
+            last_sent : None,
+            steps : 0,
+        }
+    }
+
+    pub fn load(input : &str) -> Vm {
+        Vm::parse(input).unwrap_or_else(|e| {
+            panic!("invalid intcode program: {}", e);
+        })
+    }
+
+    pub fn parse(input : &str) -> Result<Vm, ParseError> {
+        parse_memory(input).map(Vm::new)
+    }
+
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+
+    pub fn read(&self, address : usize) -> i64 {
+        self.memory.get(address).cloned().unwrap_or(0)
+    }
+
+    pub fn write(&mut self, address : usize, value : i64) -> Result<(), Fault> {
+        if address >= MEMORY_LIMIT {
+            return Err(Fault::AddressTooLarge(address));
+        }
+
+        self.store(address, value);
+        Ok(())
+    }
+
+    // Writes without checking the memory limit, for addresses that decode has already checked.
+    fn store(&mut self, address : usize, value : i64) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+
+        self.memory[address] = value;
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn push_input(&mut self, value : i64) {
+        self.inbox.push_back(value);
+    }
+
+    pub fn pop_output(&mut self) -> Option<i64> {
+        self.outbox.pop_front()
+    }
+
+    pub fn inbox(&self) -> &VecDeque<i64> {
+        &self.inbox
+    }
+
+    pub fn outbox(&self) -> &VecDeque<i64> {
+        &self.outbox
+    }
+
+    pub fn send_count(&self) -> usize {
+        self.send_count
+    }
+
+    // The last value the vm sent, even if it has since been popped from the outbox.
+    pub fn last_sent(&self) -> Option<i64> {
+        self.last_sent
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    fn opcode(&self) -> i64 {
+        self.read(self.ip) % 100
+    }
+
+    fn mode(&self, param : usize) -> Result<Mode, Fault> {
+        let divisor = [100, 1000, 10000][param - 1];
+        Mode::from_digit((self.read(self.ip) / divisor) % 10)
+    }
+
+    fn address(&self, param : usize) -> Result<usize, Fault> {
+        let raw = self.read(self.ip + param);
+        let address = match self.mode(param)? {
+            Mode::Position => raw,
+            Mode::Relative => self.relative_base.checked_add(raw).ok_or(Fault::Overflow)?,
+            Mode::Immediate => return Err(Fault::ImmediateWrite),
+        };
+
+        to_address(address)
+    }
+
+    // An address to write to, which can't be past the memory limit.
+    fn dest(&self, param : usize) -> Result<usize, Fault> {
+        let address = self.address(param)?;
+        if address < MEMORY_LIMIT {
+            Ok(address)
+        } else {
+            Err(Fault::AddressTooLarge(address))
+        }
+    }
+
+    fn param(&self, param : usize) -> Result<i64, Fault> {
+        match self.mode(param)? {
+            Mode::Immediate => Ok(self.read(self.ip + param)),
+            _ => Ok(self.read(self.address(param)?)),
+        }
+    }
+
+    fn decode(&self) -> Result<Action, Fault> {
+        Ok(match self.opcode() {
+            1 | 2 | 7 | 8 => {
+                let (a, b) = (self.param(1)?, self.param(2)?);
+                let result = match self.opcode() {
+                    1 => a.checked_add(b).ok_or(Fault::Overflow)?,
+                    2 => a.checked_mul(b).ok_or(Fault::Overflow)?,
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+
+                Action::Write(self.dest(3)?, result)
+            },
+            3 => Action::Input(self.dest(1)?),
+            4 => Action::Output(self.param(1)?),
+            5 | 6 => {
+                let cond = self.param(1)? != 0;
+                if cond == (self.opcode() == 5) {
+                    Action::Jump(to_address(self.param(2)?)?)
+                } else {
+                    Action::Jump(self.ip + 3)
+                }
+            },
+            9 => Action::AdjustBase(self.relative_base.checked_add(self.param(1)?).ok_or(Fault::Overflow)?),
+            99 => Action::Halt,
+            opcode => return Err(Fault::InvalidOpcode(opcode)),
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        match self.decode() {
+            Ok(Action::Halt) => Status::Halted,
+            Ok(Action::Input(_)) if self.inbox.is_empty() => Status::Blocked,
+            Ok(_) => Status::Ready,
+            Err(fault) => Status::Faulted(self.ip, fault),
+        }
+    }
+
+    // Executes one instruction if possible. Returns Ready if it did, or else why it couldn't.
+    fn advance(&mut self) -> Status {
+        let action = match self.decode() {
+            Ok(action) => action,
+            Err(fault) => return Status::Faulted(self.ip, fault),
+        };
+
+        let next_ip = match action {
+            Action::Write(dest, value) => {
+                self.store(dest, value);
+                self.ip + 4
+            },
+            Action::Input(dest) => {
+                match self.inbox.pop_front() {
+                    Some(value) => {
+                        self.store(dest, value);
+                        self.ip + 2
+                    },
+                    None => return Status::Blocked,
+                }
+            },
+            Action::Output(value) => {
+                self.outbox.push_back(value);
+                self.send_count += 1;
+                self.last_sent = Some(value);
+                self.ip + 2
+            },
+            Action::Jump(target) => target,
+            Action::AdjustBase(base) => {
+                self.relative_base = base;
+                self.ip + 2
+            },
+            Action::Halt => return Status::Halted,
+        };
+
+        self.ip = next_ip;
+        self.steps += 1;
+        Status::Ready
+    }
+
+    // Executes at most one instruction. Doesn't advance if the vm is blocked or halted.
+    pub fn step(&mut self) -> Status {
+        match self.advance() {
+            Status::Ready => self.status(),
+            status => status,
+        }
+    }
+
+    // Runs until the vm needs input it doesn't have, halts, or faults.
+    pub fn run(&mut self) -> Status {
+        loop {
+            let status = self.advance();
+            if status != Status::Ready {
+                return status;
+            }
+        }
+    }
+
+    // Runs until the next output is available and returns it, or None if the vm blocks, halts or
+    // faults first. Can be called again to resume.
+    pub fn next_output(&mut self) -> Option<i64> {
+        loop {
+            if let Some(value) = self.pop_output() {
+                return Some(value);
+            }
+
+            if self.advance() != Status::Ready {
+                return None;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::InvalidOpcode(opcode) => write!(f, "invalid opcode {}", opcode),
+            Fault::InvalidMode(mode) => write!(f, "invalid parameter mode {}", mode),
+            Fault::ImmediateWrite => write!(f, "write to an immediate mode parameter"),
+            Fault::NegativeAddress(address) => write!(f, "negative address {}", address),
+            Fault::AddressTooLarge(address) => write!(f, "address {} is past the memory limit", address),
+            Fault::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+fn to_address(address : i64) -> Result<usize, Fault> {
+    if address >= 0 {
+        Ok(address as usize)
+    } else {
+        Err(Fault::NegativeAddress(address))
+    }
+}
+
+impl Status {
+    // The Scheduler only knows about aocisa machines, so a faulted vm looks halted to it. It
+    // won't run again either way.
+    fn machine_status(self) -> MachineStatus {
+        match self {
+            Status::Ready => MachineStatus::Ready,
+            Status::Blocked => MachineStatus::Blocked,
+            Status::Halted | Status::Faulted(..) => MachineStatus::Halted,
+        }
+    }
+}
+
+impl Process for Vm {
+    fn status(&self) -> MachineStatus {
+        Vm::status(self).machine_status()
+    }
+
+    fn run(&mut self) -> MachineStatus {
+        Vm::run(self).machine_status()
+    }
+
+    fn push_input(&mut self, value : i64) {
+        Vm::push_input(self, value)
+    }
+
+    fn pop_output(&mut self) -> Option<i64> {
+        Vm::pop_output(self)
+    }
+
+    fn steps(&self) -> u64 {
+        Vm::steps(self)
+    }
+
+    fn send_count(&self) -> usize {
+        Vm::send_count(self)
+    }
+}
+
+// Runs a copy of the program per phase setting, each one feeding the next, with the last one
+// looping back to the first, as in 2019 day 7. The first vm also gets an initial input of 0.
+// Returns the last value the final vm sent, or None if it never sent one, or if the vms didn't all
+// halt.
+pub fn run_amplifiers(memory : &[i64], phases : &[i64]) -> Option<i64> {
+    let vms = phases.iter().map(|phase| {
+        let mut vm = Vm::new(memory.to_vec());
+        vm.push_input(*phase);
+        vm
+    }).collect();
+
+    let mut scheduler = Scheduler::new(vms);
+    scheduler.machines_mut()[0].push_input(0);
+    if scheduler.run() != SchedulerOutcome::Halted || scheduler.machines().iter().any(|vm| vm.status() != Status::Halted) {
+        return None;
+    }
+
+    scheduler.machines().last().and_then(Vm::last_sent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_with_input(program : &str, input : i64) -> Vec<i64> {
+        let mut vm = Vm::load(program);
+        vm.push_input(input);
+        assert_eq!(vm.run(), Status::Halted);
+        vm.outbox().iter().cloned().collect()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_memory("1,0, -3,99\n"), Ok(vec![1, 0, -3, 99]));
+        assert_eq!(parse_memory("1,0,x3,99"), Err(ParseError::new(5, "x3", &[VALUE_FORM])));
+    }
+
+    #[test]
+    fn add_mul() {
+        let mut vm = Vm::load("1,9,10,3,2,3,11,0,99,30,40,50");
+        assert_eq!(vm.run(), Status::Halted);
+        assert_eq!(vm.memory(), &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]);
+    }
+
+    #[test]
+    fn modes() {
+        let mut vm = Vm::load("1002,4,3,4,33");
+        assert_eq!(vm.run(), Status::Halted);
+        assert_eq!(vm.read(4), 99);
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(run_with_input("3,9,8,9,10,9,4,9,99,-1,8", 8), vec![1]);
+        assert_eq!(run_with_input("3,9,8,9,10,9,4,9,99,-1,8", 7), vec![0]);
+        assert_eq!(run_with_input("3,3,1107,-1,8,3,4,3,99", 5), vec![1]);
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(run_with_input("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9", 0), vec![0]);
+        assert_eq!(run_with_input("3,3,1105,-1,9,1101,0,0,12,4,12,99,1", 5), vec![1]);
+    }
+
+    #[test]
+    fn relative_base_quine() {
+        let program = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut vm = Vm::load(program);
+        assert_eq!(vm.run(), Status::Halted);
+        assert_eq!(vm.outbox().iter().cloned().collect::<Vec<i64>>(), parse_memory(program).unwrap());
+    }
+
+    #[test]
+    fn large_numbers() {
+        let mut vm = Vm::load("104,1125899906842624,99");
+        assert_eq!(vm.next_output(), Some(1125899906842624));
+        assert_eq!(vm.next_output(), None);
+        assert_eq!(vm.status(), Status::Halted);
+    }
+
+    #[test]
+    fn pause_on_input() {
+        let mut vm = Vm::load("3,0,4,0,3,0,4,0,99");
+        assert_eq!(vm.run(), Status::Blocked);
+        vm.push_input(7);
+        assert_eq!(vm.next_output(), Some(7));
+        assert_eq!(vm.next_output(), None);
+        assert_eq!(vm.status(), Status::Blocked);
+        vm.push_input(8);
+        assert_eq!(vm.next_output(), Some(8));
+        assert_eq!(vm.run(), Status::Halted);
+    }
+
+    #[test]
+    fn amplifiers() {
+        let memory = parse_memory("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+        assert_eq!(run_amplifiers(&memory, &[4, 3, 2, 1, 0]), Some(43210));
+    }
+
+    #[test]
+    fn amplifier_feedback_loop() {
+        let memory = parse_memory("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5").unwrap();
+        assert_eq!(run_amplifiers(&memory, &[9, 8, 7, 6, 5]), Some(139629729));
+    }
+
+    #[test]
+    fn faults() {
+        let run = |program| {
+            let mut vm = Vm::load(program);
+            (vm.run(), vm.steps())
+        };
+
+        assert_eq!(run("1101,1,1,0,42"), (Status::Faulted(4, Fault::InvalidOpcode(42)), 1));
+        assert_eq!(run("301,0,0,0,99"), (Status::Faulted(0, Fault::InvalidMode(3)), 0));
+        assert_eq!(run("11101,1,1,0,99"), (Status::Faulted(0, Fault::ImmediateWrite), 0));
+        assert_eq!(run("1,-1,0,0,99"), (Status::Faulted(0, Fault::NegativeAddress(-1)), 0));
+        assert_eq!(run("1105,1,-3,99"), (Status::Faulted(0, Fault::NegativeAddress(-3)), 0));
+        assert_eq!(run("1101,9223372036854775807,1,0,99"), (Status::Faulted(0, Fault::Overflow), 0));
+        assert_eq!(run("1102,4611686018427387904,2,0,99"), (Status::Faulted(0, Fault::Overflow), 0));
+        assert_eq!(run("109,9223372036854775807,109,1,99"), (Status::Faulted(2, Fault::Overflow), 1));
+        assert_eq!(run("1101,1,1,1000000000000,99"), (Status::Faulted(0, Fault::AddressTooLarge(1000000000000)), 0));
+        assert_eq!(run("109,1,99"), (Status::Halted, 1));
+
+        // A fault leaves memory alone, so fixing it lets the vm carry on.
+        let mut vm = Vm::load("1,5,6,0,99,9223372036854775807,1");
+        assert_eq!(vm.status(), Status::Faulted(0, Fault::Overflow));
+        assert_eq!(vm.step(), Status::Faulted(0, Fault::Overflow));
+        assert_eq!(vm.memory()[0], 1);
+        assert_eq!(vm.write(6, -1), Ok(()));
+        assert_eq!(vm.run(), Status::Halted);
+        assert_eq!(vm.read(0), i64::MAX - 1);
+
+        assert_eq!(vm.write(MEMORY_LIMIT, 1), Err(Fault::AddressTooLarge(MEMORY_LIMIT)));
+        assert_eq!(vm.memory().len(), 7);
+        assert_eq!(format!("{}", Fault::InvalidOpcode(42)), "invalid opcode 42");
+    }
+
+    #[test]
+    fn amplifier_failures() {
+        // Nothing sent.
+        assert_eq!(run_amplifiers(&[99], &[5]), None);
+        assert_eq!(run_amplifiers(&[3, 0, 99], &[5, 6]), None);
+
+        // Sent something, but then faulted or deadlocked.
+        assert_eq!(run_amplifiers(&[3, 0, 4, 0, 42], &[5, 6]), None);
+        assert_eq!(run_amplifiers(&[3, 0, 4, 0, 3, 0, 3, 0, 3, 0, 99], &[5]), None);
+        assert_eq!(run_amplifiers(&[3, 0, 4, 0, 3, 1, 99], &[5, 6]), Some(6));
+    }
+
+    #[test]
+    fn scheduler_sees_faults_as_halts() {
+        let mut scheduler = Scheduler::new(vec![Vm::load("99"), Vm::load("42")]);
+        assert_eq!(scheduler.run(), SchedulerOutcome::Halted);
+        assert_eq!(scheduler.machines()[1].status(), Status::Faulted(0, Fault::InvalidOpcode(42)));
+    }
+
+    #[test]
+    fn deadlock() {
+        // Both wait for input that never comes.
+        let mut scheduler = Scheduler::new(vec![Vm::load("3,0,99"), Vm::load("3,0,99")]);
+        assert_eq!(scheduler.run(), SchedulerOutcome::Deadlocked);
+    }
+}
@@ -1,7 +1,12 @@
 use super::*;
+use std::thread;
+use grid::Grid;
+use onoffpixel::OnOffPixel;
+use bit_iterator::BitIterator;
 
 const KNOT_HASH_RING_SIZE : u32 = 256;
 const KNOT_HASH_BLOCK_SIZE : usize = 16;
+const DISK_ROWS : usize = 128;
 
 pub struct Ring {
     ring : Vec<u8>,
@@ -70,9 +75,40 @@ pub fn knot_hash_as_hex(input : &str) -> String {
     numbers_to_hex_string(knot_hash(input).iter().cloned())
 }
 
+// The used and free squares of the disk in 2017 day 14. Row n is the knot hash of "key-n", with
+// each bit, most significant first, being one square. Rows are hashed on separate threads.
+pub fn disk_grid(key : &str) -> Grid<OnOffPixel> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(DISK_ROWS);
+    let rows_per_thread = DISK_ROWS.div_ceil(threads);
+
+    let mut rows = vec![vec![]; DISK_ROWS];
+    thread::scope(|scope| {
+        for (chunk, chunk_rows) in rows.chunks_mut(rows_per_thread).enumerate() {
+            scope.spawn(move || {
+                for (offset, row) in chunk_rows.iter_mut().enumerate() {
+                    *row = disk_row(key, chunk * rows_per_thread + offset);
+                }
+            });
+        }
+    });
+
+    Grid::from_rows(rows)
+}
+
+fn disk_row(key : &str, row : usize) -> Vec<OnOffPixel> {
+    knot_hash(&format!("{}-{}", key, row)).into_iter().flat_map(|byte| BitIterator::new(byte).rev()).map(|bit| {
+        if bit == 1 {
+            OnOffPixel::On
+        } else {
+            OnOffPixel::Off
+        }
+    }).collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use grid::Stencil;
 
     #[test]
     fn reduce_default_2() {
@@ -99,4 +135,27 @@ mod test {
         assert_eq!(knot_hash_as_hex("1,2,3"), "3efbe78a8d82f29979031a4aa0b16a9d");
         assert_eq!(knot_hash_as_hex("1,2,4"), "63960835bcdc130f0b66d7ff4f6a5a8e");
     }
+
+    #[test]
+    fn disk() {
+        let disk = disk_grid("flqrgnkx");
+        assert_eq!((disk.size_x(), disk.size_y()), (128, 128));
+
+        // The corner shown in the puzzle.
+        let corner = disk.rows().take(8).map(|row| row.iter().take(8).map(|square| format!("{}", square)).collect::<String>()).collect::<Vec<_>>();
+        assert_eq!(corner, vec![
+            "##.#.#..",
+            ".#.#.#.#",
+            "....#.#.",
+            "#.#.##.#",
+            ".##.#...",
+            "##..#..#",
+            ".#...#..",
+            "##.#.##.",]);
+
+        assert_eq!(disk.iter().filter(|square| square.is_on()).count(), 8108);
+
+        let components = disk.components(Stencil::Orthogonal, |a, b| a == b);
+        assert_eq!(components.regions().iter().filter(|region| disk[region.seed].is_on()).count(), 1242);
+    }
 }
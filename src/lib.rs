@@ -1,5 +1,3 @@
-#![feature(nll)]
-
 use std::io::prelude::*;
 use std::env;
 
@@ -11,9 +9,15 @@ pub mod list;
 pub mod knot_hash;
 pub mod bit_iterator;
 pub mod grid;
+pub mod grid_search;
+pub mod grid_regions;
+pub mod search;
+pub mod point;
+pub mod sparse_grid;
 pub mod onoffpixel;
 pub mod direction;
 pub mod aocisa;
+pub mod intcode;
 
 pub fn read_all_stdin() -> String {
     let mut contents = String::new();
@@ -50,7 +54,7 @@ pub fn any_eq<T>(iter : impl Iterator<Item = T>, item : &T) -> bool
     position_eq(iter, item).is_some()
 }
 
-pub fn reverse_circular_vec_segment<T>(v : &mut Vec<T>, start_index : usize, length : usize) {
+pub fn reverse_circular_vec_segment<T>(v : &mut [T], start_index : usize, length : usize) {
     if length > 0 {
         let mut start_index = start_index;
 
@@ -84,7 +84,7 @@ impl<T> ListNode<T> {
 
         nodes_tail.upgrade().unwrap().borrow_mut().next = Some(Rc::clone(&list_next));
 
-        self.next = Some(Rc::clone(&nodes_head));
+        self.next = Some(Rc::clone(nodes_head));
         nodes_head.borrow_mut().prev = self.this.clone();
     }
 
@@ -110,7 +110,7 @@ impl<T> Iterator for ListNodeIterator<T> {
         } else {
             let ret = Some(Rc::clone(&self.current));
 
-            if Rc::ptr_eq(&self.current.borrow().next.as_ref().unwrap(), &self.head) {
+            if Rc::ptr_eq(self.current.borrow().next.as_ref().unwrap(), &self.head) {
                 self.at_end = true;
             }
 
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fmt;
 
 #[derive(Clone, PartialEq, Debug)]
@@ -29,6 +30,19 @@ impl OnOffPixel {
     }
 }
 
+// Stricter than parse, so that Grid::parse catches stray characters.
+impl TryFrom<char> for OnOffPixel {
+    type Error = char;
+
+    fn try_from(ch : char) -> Result<OnOffPixel, char> {
+        match ch {
+            '.' => Ok(OnOffPixel::Off),
+            '#' => Ok(OnOffPixel::On),
+            _ => Err(ch),
+        }
+    }
+}
+
 impl fmt::Display for OnOffPixel {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", if self == &OnOffPixel::Off { '.' } else { '#' })
@@ -45,6 +59,13 @@ mod test {
         assert_eq!(OnOffPixel::parse('#'), OnOffPixel::On);
     }
 
+    #[test]
+    fn try_from() {
+        assert_eq!(OnOffPixel::try_from('.'), Ok(OnOffPixel::Off));
+        assert_eq!(OnOffPixel::try_from('#'), Ok(OnOffPixel::On));
+        assert_eq!(OnOffPixel::try_from('x'), Err('x'));
+    }
+
     #[test]
     fn display() {
         assert_eq!(format!("{}", OnOffPixel::Off), ".");
@@ -59,7 +80,7 @@ mod test {
 
     #[test]
     fn is_on() {
-        assert_eq!(OnOffPixel::On.is_on(), true);
-        assert_eq!(OnOffPixel::Off.is_on(), false);
+        assert!(OnOffPixel::On.is_on());
+        assert!(!OnOffPixel::Off.is_on());
     }
 }
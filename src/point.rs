@@ -0,0 +1,121 @@
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// A location or offset on a grid. As with Grid, y grows downward.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Default)]
+pub struct Point {
+    pub x : i32,
+    pub y : i32,
+}
+
+impl Point {
+    pub fn new(x : i32, y : i32) -> Point {
+        Point {
+            x,
+            y,
+        }
+    }
+
+    pub fn manhattan_distance(&self, other : Point) -> u32 {
+        (self.x - other.x).unsigned_abs() + (self.y - other.y).unsigned_abs()
+    }
+
+    // 90 degrees clockwise as drawn, so (1, 0) becomes (0, 1).
+    pub fn rotate_right(&self) -> Point {
+        Point::new(-self.y, self.x)
+    }
+
+    pub fn rotate_left(&self) -> Point {
+        Point::new(self.y, -self.x)
+    }
+}
+
+impl From<(i32, i32)> for Point {
+    fn from((x, y) : (i32, i32)) -> Point {
+        Point::new(x, y)
+    }
+}
+
+impl From<Point> for (i32, i32) {
+    fn from(point : Point) -> (i32, i32) {
+        (point.x, point.y)
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other : Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other : Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Neg for Point {
+    type Output = Point;
+
+    fn neg(self) -> Point {
+        Point::new(-self.x, -self.y)
+    }
+}
+
+impl Mul<i32> for Point {
+    type Output = Point;
+
+    fn mul(self, factor : i32) -> Point {
+        Point::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl AddAssign for Point {
+    fn add_assign(&mut self, other : Point) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Point {
+    fn sub_assign(&mut self, other : Point) {
+        *self = *self - other;
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let mut point = Point::new(1, 2);
+        assert_eq!(point + Point::new(3, -4), Point::new(4, -2));
+        assert_eq!(point - Point::new(3, -4), Point::new(-2, 6));
+        assert_eq!(-point, Point::new(-1, -2));
+        assert_eq!(point * 3, Point::new(3, 6));
+
+        point += Point::new(1, 1);
+        assert_eq!(point, Point::from((2, 3)));
+        point -= Point::new(2, 0);
+        assert_eq!(<(i32, i32)>::from(point), (0, 3));
+    }
+
+    #[test]
+    fn distance_and_rotation() {
+        assert_eq!(Point::new(-1, 2).manhattan_distance(Point::new(3, -3)), 9);
+        assert_eq!(Point::new(1, 0).rotate_right(), Point::new(0, 1));
+        assert_eq!(Point::new(1, 0).rotate_left(), Point::new(0, -1));
+        assert_eq!(Point::new(2, 5).rotate_right().rotate_left(), Point::new(2, 5));
+        assert_eq!(format!("{}", Point::new(-1, 2)), "(-1, 2)");
+    }
+}
@@ -0,0 +1,569 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+// The states along a route, from the start to the goal inclusive, and what it cost. For the
+// unweighted searches, the cost is the number of steps.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Path<S> {
+    pub states : Vec<S>,
+    pub cost : u64,
+}
+
+// Remembers which states a search has already seen.
+pub trait Visited<S> {
+    // Returns false if the state was already there.
+    fn insert(&mut self, state : &S) -> bool;
+
+    fn contains(&mut self, state : &S) -> bool;
+}
+
+// Remembers whole states.
+pub struct HashVisited<S> {
+    set : HashSet<S>,
+}
+
+// Remembers only part of each state, for when two states with the same key are interchangeable,
+// like the same position with the same keys collected in a different order.
+pub struct KeyVisited<K, F> {
+    set : HashSet<K>,
+    key : F,
+}
+
+// Remembers states through a dense index, which is much faster than hashing when states are
+// small, like coordinates or bitmasks.
+pub struct BitsetVisited<F> {
+    bits : Vec<u64>,
+    index : F,
+}
+
+impl<S> HashVisited<S>
+where S : Hash + Eq {
+    pub fn new() -> HashVisited<S> {
+        HashVisited {
+            set : HashSet::new(),
+        }
+    }
+}
+
+impl<S> Default for HashVisited<S>
+where S : Hash + Eq {
+    fn default() -> HashVisited<S> {
+        HashVisited::new()
+    }
+}
+
+impl<S> Visited<S> for HashVisited<S>
+where S : Hash + Eq + Clone {
+    fn insert(&mut self, state : &S) -> bool {
+        !self.set.contains(state) && self.set.insert(state.clone())
+    }
+
+    fn contains(&mut self, state : &S) -> bool {
+        self.set.contains(state)
+    }
+}
+
+impl<K, F> KeyVisited<K, F>
+where K : Hash + Eq {
+    pub fn new(key : F) -> KeyVisited<K, F> {
+        KeyVisited {
+            set : HashSet::new(),
+            key,
+        }
+    }
+}
+
+impl<S, K, F> Visited<S> for KeyVisited<K, F>
+where K : Hash + Eq,
+      F : FnMut(&S) -> K {
+    fn insert(&mut self, state : &S) -> bool {
+        self.set.insert((self.key)(state))
+    }
+
+    fn contains(&mut self, state : &S) -> bool {
+        self.set.contains(&(self.key)(state))
+    }
+}
+
+impl<F> BitsetVisited<F> {
+    pub fn new(index : F) -> BitsetVisited<F> {
+        BitsetVisited {
+            bits : vec![],
+            index,
+        }
+    }
+}
+
+impl<S, F> Visited<S> for BitsetVisited<F>
+where F : FnMut(&S) -> usize {
+    fn insert(&mut self, state : &S) -> bool {
+        let index = (self.index)(state);
+        let (word, bit) = (index / 64, 1u64 << (index % 64));
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+
+        let new = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        new
+    }
+
+    fn contains(&mut self, state : &S) -> bool {
+        let index = (self.index)(state);
+        self.bits.get(index / 64).is_some_and(|word| word & (1u64 << (index % 64)) != 0)
+    }
+}
+
+// Every state reached, with the index of the one it was reached from, so paths can be rebuilt
+// without the states having to be hashable.
+struct Arena<S> {
+    nodes : Vec<(S, Option<usize>)>,
+}
+
+impl<S> Arena<S>
+where S : Clone {
+    fn new() -> Arena<S> {
+        Arena {
+            nodes : vec![],
+        }
+    }
+
+    fn push(&mut self, state : S, parent : Option<usize>) -> usize {
+        self.nodes.push((state, parent));
+        self.nodes.len() - 1
+    }
+
+    fn path(&self, mut index : usize, cost : u64) -> Path<S> {
+        let mut states = vec![self.nodes[index].0.clone()];
+        while let Some(parent) = self.nodes[index].1 {
+            states.push(self.nodes[parent].0.clone());
+            index = parent;
+        }
+        states.reverse();
+
+        Path {
+            states,
+            cost,
+        }
+    }
+}
+
+// Fewest steps from start to any state matching is_goal.
+pub fn bfs<S, V, N, I, G>(start : S, mut visited : V, mut successors : N, mut is_goal : G) -> Option<Path<S>>
+where S : Clone,
+      V : Visited<S>,
+      N : FnMut(&S) -> I,
+      I : IntoIterator<Item = S>,
+      G : FnMut(&S) -> bool {
+    let mut arena = Arena::new();
+    let mut queue = VecDeque::new();
+    visited.insert(&start);
+    if is_goal(&start) {
+        return Some(Path { states : vec![start], cost : 0 });
+    }
+
+    queue.push_back((arena.push(start, None), 0));
+    while let Some((index, depth)) = queue.pop_front() {
+        for next in successors(&arena.nodes[index].0) {
+            if visited.insert(&next) {
+                let goal = is_goal(&next);
+                let next_index = arena.push(next, Some(index));
+                if goal {
+                    return Some(arena.path(next_index, depth + 1));
+                }
+
+                queue.push_back((next_index, depth + 1));
+            }
+        }
+    }
+
+    None
+}
+
+// Lowest total cost from start to any state matching is_goal, with successors giving the cost of
+// each move.
+pub fn dijkstra<S, V, N, I, G>(start : S, visited : V, successors : N, is_goal : G) -> Option<Path<S>>
+where S : Clone,
+      V : Visited<S>,
+      N : FnMut(&S) -> I,
+      I : IntoIterator<Item = (S, u64)>,
+      G : FnMut(&S) -> bool {
+    astar(start, visited, successors, |_| 0, is_goal)
+}
+
+// Like dijkstra, but guided by a heuristic. Since each state is only expanded once, the heuristic
+// has to be consistent: never more than the cost of a move plus the heuristic after it.
+pub fn astar<S, V, N, I, H, G>(start : S, mut visited : V, mut successors : N, mut heuristic : H, mut is_goal : G) -> Option<Path<S>>
+where S : Clone,
+      V : Visited<S>,
+      N : FnMut(&S) -> I,
+      I : IntoIterator<Item = (S, u64)>,
+      H : FnMut(&S) -> u64,
+      G : FnMut(&S) -> bool {
+    let mut arena = Arena::new();
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((heuristic(&start), 0, arena.push(start, None))));
+
+    while let Some(Reverse((_, cost, index))) = open.pop() {
+        // Already expanded through a cheaper route.
+        if !visited.insert(&arena.nodes[index].0) {
+            continue;
+        }
+
+        if is_goal(&arena.nodes[index].0) {
+            return Some(arena.path(index, cost));
+        }
+
+        // Successors that were already expanded can't be reached more cheaply now, so they're left
+        // out of the arena rather than being pushed only to be skipped.
+        for (next, step) in successors(&arena.nodes[index].0) {
+            if visited.contains(&next) {
+                continue;
+            }
+
+            let estimate = cost + step + heuristic(&next);
+            open.push(Reverse((estimate, cost + step, arena.push(next, Some(index)))));
+        }
+    }
+
+    None
+}
+
+// Iterative deepening A*, which only keeps the current path in memory, for state spaces too big to
+// remember. The heuristic must never overestimate. States repeated within the current path are
+// skipped, but nothing stops the same state being searched again by another route.
+pub fn ida_star<S, N, I, H, G>(start : S, mut successors : N, mut heuristic : H, mut is_goal : G) -> Option<Path<S>>
+where S : PartialEq,
+      N : FnMut(&S) -> I,
+      I : IntoIterator<Item = (S, u64)>,
+      H : FnMut(&S) -> u64,
+      G : FnMut(&S) -> bool {
+    let mut bound = heuristic(&start);
+    let mut path = vec![start];
+    loop {
+        match ida_star_from(&mut path, 0, bound, &mut successors, &mut heuristic, &mut is_goal) {
+            Deepen::Found(cost) => return Some(Path { states : path, cost }),
+            Deepen::Exceeded(next_bound) => bound = next_bound,
+            Deepen::Exhausted => return None,
+        }
+    }
+}
+
+enum Deepen {
+    Found(u64),
+
+    // The smallest estimate that was over the bound, to use as the next bound.
+    Exceeded(u64),
+
+    Exhausted,
+}
+
+fn ida_star_from<S, N, I, H, G>(path : &mut Vec<S>, cost : u64, bound : u64, successors : &mut N, heuristic : &mut H, is_goal : &mut G) -> Deepen
+where S : PartialEq,
+      N : FnMut(&S) -> I,
+      I : IntoIterator<Item = (S, u64)>,
+      H : FnMut(&S) -> u64,
+      G : FnMut(&S) -> bool {
+    let state = path.last().unwrap();
+    let estimate = cost + heuristic(state);
+    if estimate > bound {
+        return Deepen::Exceeded(estimate);
+    }
+
+    if is_goal(state) {
+        return Deepen::Found(cost);
+    }
+
+    let mut result = Deepen::Exhausted;
+    for (next, step) in successors(state) {
+        if path.contains(&next) {
+            continue;
+        }
+
+        path.push(next);
+        match ida_star_from(path, cost + step, bound, successors, heuristic, is_goal) {
+            Deepen::Found(cost) => return Deepen::Found(cost),
+            Deepen::Exceeded(over) => {
+                result = match result {
+                    Deepen::Exceeded(lowest) => Deepen::Exceeded(lowest.min(over)),
+                    _ => Deepen::Exceeded(over),
+                };
+            },
+            Deepen::Exhausted => {},
+        }
+        path.pop();
+    }
+
+    result
+}
+
+// Searches from both ends at once, which explores far fewer states when the graph branches a lot.
+// predecessors has to give every state with a move to the given one; for undirected graphs, that's
+// the same as successors.
+pub fn bidirectional_bfs<S, N, NI, P, PI>(start : S, goal : S, mut successors : N, mut predecessors : P) -> Option<Path<S>>
+where S : Hash + Eq + Clone,
+      N : FnMut(&S) -> NI,
+      NI : IntoIterator<Item = S>,
+      P : FnMut(&S) -> PI,
+      PI : IntoIterator<Item = S> {
+    if start == goal {
+        return Some(Path { states : vec![start], cost : 0 });
+    }
+
+    // Each side maps states to the state it reached them from, and their distance from its end.
+    let mut forward : HashMap<S, (Option<S>, u64)> = HashMap::new();
+    let mut backward : HashMap<S, (Option<S>, u64)> = HashMap::new();
+    forward.insert(start.clone(), (None, 0));
+    backward.insert(goal.clone(), (None, 0));
+    let mut forward_layer = vec![start];
+    let mut backward_layer = vec![goal];
+
+    while !forward_layer.is_empty() && !backward_layer.is_empty() {
+        let expand_forward = forward_layer.len() <= backward_layer.len();
+        let (layer, this_side, other_side) = if expand_forward {
+            (&mut forward_layer, &mut forward, &backward)
+        } else {
+            (&mut backward_layer, &mut backward, &forward)
+        };
+
+        // The first meeting found isn't necessarily the best one, so finish the whole layer.
+        let mut best : Option<(u64, S)> = None;
+        let mut next_layer = vec![];
+        for state in layer.drain(..) {
+            let depth = this_side[&state].1;
+            let neighbours : Vec<S> = if expand_forward {
+                successors(&state).into_iter().collect()
+            } else {
+                predecessors(&state).into_iter().collect()
+            };
+
+            for next in neighbours {
+                if this_side.contains_key(&next) {
+                    continue;
+                }
+
+                if let Some(&(_, other_depth)) = other_side.get(&next) {
+                    let total = depth + 1 + other_depth;
+                    if best.as_ref().is_none_or(|&(lowest, _)| total < lowest) {
+                        best = Some((total, next.clone()));
+                    }
+                }
+
+                this_side.insert(next.clone(), (Some(state.clone()), depth + 1));
+                next_layer.push(next);
+            }
+        }
+
+        if let Some((cost, meeting)) = best {
+            return Some(Path {
+                states : join_halves(&forward, &backward, meeting),
+                cost,
+            });
+        }
+
+        *layer = next_layer;
+    }
+
+    None
+}
+
+fn join_halves<S>(forward : &HashMap<S, (Option<S>, u64)>, backward : &HashMap<S, (Option<S>, u64)>, meeting : S) -> Vec<S>
+where S : Hash + Eq + Clone {
+    let mut states = vec![meeting.clone()];
+    while let Some(previous) = forward[states.last().unwrap()].0.clone() {
+        states.push(previous);
+    }
+    states.reverse();
+
+    let mut state = meeting;
+    while let Some(next) = backward[&state].0.clone() {
+        states.push(next.clone());
+        state = next;
+    }
+
+    states
+}
+
+// The lowest cost from start to a goal, and how many different ways there are to get there at that
+// cost. Each move counts separately, even two moves between the same pair of states. Costs have to
+// be positive; for unweighted graphs, make every move cost 1. The count stops at u64::MAX rather
+// than overflowing, so u64::MAX means at least that many.
+pub fn count_shortest_paths<S, N, I, G>(start : S, mut successors : N, mut is_goal : G) -> Option<(u64, u64)>
+where S : Hash + Eq + Clone,
+      N : FnMut(&S) -> I,
+      I : IntoIterator<Item = (S, u64)>,
+      G : FnMut(&S) -> bool {
+    let mut best : HashMap<S, (u64, u64)> = HashMap::new();
+    let mut done = HashSet::new();
+    let mut open = BinaryHeap::new();
+    let mut found : Option<(u64, u64)> = None;
+    best.insert(start.clone(), (0, 1));
+    open.push(Reverse((0, 0usize)));
+    let mut states = vec![start];
+
+    while let Some(Reverse((cost, index))) = open.pop() {
+        // Once a goal is settled, only other goals at the same cost can add to the count.
+        if found.is_some_and(|(goal_cost, _)| cost > goal_cost) {
+            break;
+        }
+
+        let state = states[index].clone();
+        if !done.insert(state.clone()) {
+            continue;
+        }
+
+        let (_, ways) = best[&state];
+        if is_goal(&state) {
+            found = Some((cost, found.map_or(0, |(_, count)| count).saturating_add(ways)));
+            continue;
+        }
+
+        for (next, step) in successors(&state) {
+            let next_cost = cost + step;
+            match best.get(&next).cloned() {
+                Some((known, count)) if known == next_cost => {
+                    best.insert(next, (known, count.saturating_add(ways)));
+                },
+                Some((known, _)) if known < next_cost => {},
+                _ => {
+                    best.insert(next.clone(), (next_cost, ways));
+                    states.push(next);
+                    open.push(Reverse((next_cost, states.len() - 1)));
+                },
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn double_or_increment(n : &u32) -> Vec<u32> {
+        vec![n + 1, n * 2]
+    }
+
+    #[test]
+    fn bfs_strategies() {
+        let expected = Some(Path { states : vec![1, 2, 4, 5, 10], cost : 4 });
+        assert_eq!(bfs(1, HashVisited::new(), double_or_increment, |&n| n == 10), expected);
+        assert_eq!(bfs(1, BitsetVisited::new(|&n : &u32| n as usize), |&n| double_or_increment(&n).into_iter().filter(|&n| n < 1000).collect::<Vec<_>>(), |&n| n == 10), expected);
+        assert_eq!(bfs(3, HashVisited::new(), |&n : &u32| if n < 20 { vec![n + 2] } else { vec![] }, |&n| n == 10), None);
+        assert_eq!(bfs(3, HashVisited::new(), |&n : &u32| vec![n + 2], |&n| n == 3).unwrap().cost, 0);
+
+        // The state carries a move counter that doesn't matter for where it can go next.
+        let path = bfs((0i32, 0u32), KeyVisited::new(|&(position, _) : &(i32, u32)| position), |&(position, moves)| {
+            vec![((position + 3).min(10), moves + 1), (position - 1, moves + 1)]
+        }, |&(position, _)| position == 7).unwrap();
+        assert_eq!(path.cost, 5);
+        assert_eq!(path.states.last(), Some(&(7, 5)));
+    }
+
+    #[test]
+    fn visited() {
+        let mut visited = BitsetVisited::new(|&n : &usize| n);
+        assert!(visited.insert(&200));
+        assert!(!visited.insert(&200));
+        assert!(visited.insert(&3));
+        assert!(visited.contains(&3));
+        assert!(!visited.contains(&4));
+        assert!(!visited.contains(&100000));
+
+        let mut visited = KeyVisited::new(|s : &&str| s.len());
+        assert!(visited.insert(&"ab"));
+        assert!(!visited.insert(&"cd"));
+        assert!(visited.contains(&"ef"));
+        assert!(!visited.contains(&"g"));
+
+        let mut visited = HashVisited::default();
+        assert!(!visited.contains(&'a'));
+        assert!(visited.insert(&'a'));
+        assert!(visited.contains(&'a'));
+    }
+
+    #[test]
+    fn weighted() {
+        // a -1-> b -1-> c -1-> d, with a shortcut a -5-> d and a trap a -1-> e -10-> d.
+        let edges = |&s : &char| -> Vec<(char, u64)> {
+            match s {
+                'a' => vec![('d', 5), ('e', 1), ('b', 1)],
+                'b' => vec![('c', 1)],
+                'c' => vec![('d', 1)],
+                'e' => vec![('d', 10)],
+                _ => vec![],
+            }
+        };
+
+        let expected = Some(Path { states : vec!['a', 'b', 'c', 'd'], cost : 3 });
+        assert_eq!(dijkstra('a', HashVisited::new(), edges, |&s| s == 'd'), expected);
+        assert_eq!(astar('a', HashVisited::new(), edges, |&s| if s == 'd' { 0 } else { 1 }, |&s| s == 'd'), expected);
+        assert_eq!(ida_star('a', edges, |_| 0, |&s| s == 'd'), expected);
+        assert_eq!(dijkstra('d', HashVisited::new(), edges, |&s| s == 'a'), None);
+        assert_eq!(ida_star('d', edges, |_| 0, |&s| s == 'a'), None);
+    }
+
+    #[test]
+    fn ida_star_on_a_line() {
+        // Moves of 1 or 3 either way, with a heuristic of the fewest moves if they were all 3.
+        let path = ida_star(0i32, |&n| vec![(n + 3, 1), (n + 1, 1), (n - 1, 1), (n - 3, 1)], |&n| ((10 - n).unsigned_abs() as u64).div_ceil(3), |&n| n == 10).unwrap();
+        assert_eq!(path.cost, 4);
+        assert_eq!(path.states, vec![0, 3, 6, 9, 10]);
+    }
+
+    #[test]
+    fn bidirectional() {
+        let halve_or_decrement = |&n : &u32| if n % 2 == 0 { vec![n - 1, n / 2] } else { vec![n - 1] };
+        let path = bidirectional_bfs(1, 10, double_or_increment, halve_or_decrement).unwrap();
+        assert_eq!(path.cost, 4);
+        assert_eq!(path.states.len(), 5);
+        assert_eq!((path.states[0], path.states[4]), (1, 10));
+        assert!(path.states.windows(2).all(|pair| double_or_increment(&pair[0]).contains(&pair[1])));
+
+        assert_eq!(bidirectional_bfs(1, 1000, double_or_increment, halve_or_decrement).unwrap().cost, 14);
+        assert_eq!(bidirectional_bfs(5, 5, double_or_increment, halve_or_decrement).unwrap().cost, 0);
+        let up_to_ten = |&n : &u32| if n < 10 { vec![n + 1] } else { vec![] };
+        let down_to_zero = |&n : &u32| if n > 0 { vec![n - 1] } else { vec![] };
+        assert_eq!(bidirectional_bfs(5, 2, up_to_ten, down_to_zero), None);
+    }
+
+    #[test]
+    fn counting() {
+        // 1 goes to 2 both by adding and by doubling.
+        let unweighted = |n : &u32| double_or_increment(n).into_iter().map(|n| (n, 1)).collect::<Vec<_>>();
+        assert_eq!(count_shortest_paths(1, unweighted, |&n| n == 10), Some((4, 2)));
+
+        // Paths through a 3x3 grid moving only right and down.
+        let lattice = |&(x, y) : &(u32, u32)| {
+            let mut next = vec![];
+            if x < 2 {
+                next.push(((x + 1, y), 1));
+            }
+            if y < 2 {
+                next.push(((x, y + 1), 1));
+            }
+            next
+        };
+        assert_eq!(count_shortest_paths((0, 0), lattice, |&p| p == (2, 2)), Some((4, 6)));
+
+        // Goals at the same cost all add to the count.
+        assert_eq!(count_shortest_paths((0, 0), lattice, |&(x, y)| x + y == 2 && x != 1), Some((2, 2)));
+        assert_eq!(count_shortest_paths((0, 0), lattice, |&(_, y)| y == 3), None);
+
+        // There are 80 choose 40 paths through a 41x41 grid, which is more than a u64 holds.
+        let big_lattice = |&(x, y) : &(u32, u32)| {
+            let mut next = vec![];
+            if x < 40 {
+                next.push(((x + 1, y), 1));
+            }
+            if y < 40 {
+                next.push(((x, y + 1), 1));
+            }
+            next
+        };
+        assert_eq!(count_shortest_paths((0, 0), big_lattice, |&p| p == (32, 32)), Some((64, 1832624140942590534)));
+        assert_eq!(count_shortest_paths((0, 0), big_lattice, |&p| p == (40, 40)), Some((80, u64::MAX)));
+    }
+}
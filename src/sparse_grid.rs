@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fmt;
+use grid::*;
+
+// A grid with no edges, keyed by signed coordinates. Only points that were written take up space;
+// everywhere else reads as the default value. The bounding box grows to cover every written point.
+#[derive(Clone, Debug)]
+pub struct SparseGrid<T> {
+    cells : HashMap<(i32, i32), T>,
+    default : T,
+    bounds : Option<BoundingBox>,
+}
+
+pub type InfiniteGrid<T> = SparseGrid<T>;
+
+impl<T> SparseGrid<T> {
+    pub fn new(default : T) -> SparseGrid<T> {
+        SparseGrid {
+            cells : HashMap::new(),
+            default,
+            bounds : None,
+        }
+    }
+
+    // Copies every cell of a dense grid, keeping its coordinates.
+    pub fn from_grid(grid : &Grid<T>, default : T) -> SparseGrid<T>
+    where T : Clone {
+        let mut sparse = SparseGrid::new(default);
+        for ((x, y), value) in grid.enumerate() {
+            sparse.set(x as i32, y as i32, value.clone());
+        }
+        sparse
+    }
+
+    pub fn default_value(&self) -> &T {
+        &self.default
+    }
+
+    // Never fails; unwritten points read as the default.
+    pub fn get(&self, x : i32, y : i32) -> &T {
+        self.cells.get(&(x, y)).unwrap_or(&self.default)
+    }
+
+    // Writes the default into the point first if it didn't have a value yet.
+    pub fn get_mut(&mut self, x : i32, y : i32) -> &mut T
+    where T : Clone {
+        self.include(x, y);
+        let default = &self.default;
+        self.cells.entry((x, y)).or_insert_with(|| default.clone())
+    }
+
+    pub fn set(&mut self, x : i32, y : i32, value : T) {
+        self.include(x, y);
+        self.cells.insert((x, y), value);
+    }
+
+    // Makes the point read as the default again, shrinking the bounding box if it was on an edge.
+    pub fn remove(&mut self, x : i32, y : i32) -> Option<T> {
+        let removed = self.cells.remove(&(x, y));
+        if removed.is_some() {
+            let on_edge = self.bounds.is_some_and(|b| x == b.min_x || x == b.max_x || y == b.min_y || y == b.max_y);
+            if on_edge {
+                self.bounds = None;
+                let points : Vec<(i32, i32)> = self.cells.keys().cloned().collect();
+                for (x, y) in points {
+                    self.include(x, y);
+                }
+            }
+        }
+        removed
+    }
+
+    // Whether the point was written, as opposed to reading as the default.
+    pub fn contains(&self, x : i32, y : i32) -> bool {
+        self.cells.contains_key(&(x, y))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bounds
+    }
+
+    // The written points, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &T)> {
+        self.cells.iter().map(|(location, value)| (*location, value))
+    }
+
+    // The occupied region as a dense grid, so the point at the bounding box's minimum corner ends up
+    // at 0, 0.
+    pub fn to_grid(&self) -> Grid<T>
+    where T : Clone {
+        let mut grid = Grid::new();
+        if let Some(bounds) = self.bounds {
+            for y in bounds.min_y ..= bounds.max_y {
+                grid.add_row((bounds.min_x ..= bounds.max_x).map(|x| self.get(x, y).clone()).collect());
+            }
+        }
+        grid
+    }
+
+    fn include(&mut self, x : i32, y : i32) {
+        match self.bounds {
+            Some(ref mut bounds) => bounds.include(x, y),
+            None => self.bounds = Some(BoundingBox::new(x, y)),
+        }
+    }
+}
+
+impl<T> Plane<T> for SparseGrid<T>
+where T : Clone {
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bounds
+    }
+
+    fn at(&self, x : i32, y : i32) -> Option<&T> {
+        Some(self.get(x, y))
+    }
+
+    fn at_mut(&mut self, x : i32, y : i32) -> Option<&mut T> {
+        Some(self.get_mut(x, y))
+    }
+}
+
+impl<T> PartialEq for SparseGrid<T>
+where T : PartialEq {
+    fn eq(&self, other : &SparseGrid<T>) -> bool {
+        self.default == other.default && self.cells == other.cells
+    }
+}
+
+// Draws the bounding box, with the default value wherever nothing was written.
+impl<T> fmt::Display for SparseGrid<T>
+where T : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(bounds) = self.bounds {
+            for y in bounds.min_y ..= bounds.max_y {
+                for x in bounds.min_x ..= bounds.max_x {
+                    write!(f, "{}", self.get(x, y))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use direction::Direction;
+
+    #[test]
+    fn signed_coordinates() {
+        let mut grid = SparseGrid::new('.');
+        assert_eq!(grid.bounding_box(), None);
+        assert_eq!(format!("{}", grid), "");
+
+        // Walk into negative x and y, left and then up, without any bounds checks.
+        let (mut x, mut y) = (0, 0);
+        grid.set(x, y, '#');
+        for direction in &[Direction::Left, Direction::Left, Direction::Up, Direction::Right] {
+            let (dx, dy) = direction.grid_offset();
+            x += dx;
+            y += dy;
+            grid.set(x, y, '#');
+        }
+
+        assert_eq!(grid.bounding_box(), Some(BoundingBox { min_x : -2, min_y : -1, max_x : 0, max_y : 0 }));
+        assert_eq!(format!("{}", grid), "##.\n###\n");
+        assert_eq!(*grid.get(-100, 100), '.');
+        assert_eq!(grid.len(), 5);
+
+        *grid.get_mut(1, 1) = 'o';
+        assert_eq!(format!("{}", grid), "##..\n###.\n...o\n");
+    }
+
+    #[test]
+    fn remove() {
+        let mut grid = SparseGrid::new(0);
+        grid.set(-3, 0, 1);
+        grid.set(0, 0, 2);
+        grid.set(4, 2, 3);
+
+        assert_eq!(grid.remove(0, 0), Some(2));
+        assert_eq!(grid.bounding_box(), Some(BoundingBox { min_x : -3, min_y : 0, max_x : 4, max_y : 2 }));
+        assert_eq!(grid.remove(4, 2), Some(3));
+        assert_eq!(grid.bounding_box(), Some(BoundingBox::new(-3, 0)));
+        assert_eq!(grid.remove(4, 2), None);
+        assert_eq!(grid.remove(-3, 0), Some(1));
+        assert_eq!(grid.bounding_box(), None);
+        assert!(grid.is_empty());
+    }
+
+    #[test]
+    fn dense_round_trip() {
+        let dense = Grid::<char>::parse("#.\n.#\n##").unwrap();
+        let mut sparse = SparseGrid::from_grid(&dense, '.');
+        assert_eq!(sparse.to_grid(), dense);
+
+        sparse.set(-1, -1, '#');
+        assert_eq!(sparse.to_grid(), Grid::<char>::parse("#..\n.#.\n..#\n.##").unwrap());
+    }
+
+    #[test]
+    fn plane() {
+        // The same code working on both kinds of grid.
+        fn count_on<P>(plane : &P) -> usize
+        where P : Plane<char> {
+            plane.count_where(|&ch| ch == '#')
+        }
+
+        let mut dense = Grid::<char>::parse(".#\n##").unwrap();
+        let mut sparse = SparseGrid::from_grid(&dense, '.');
+        assert_eq!(count_on(&dense), 3);
+        assert_eq!(count_on(&sparse), 3);
+
+        assert_eq!(dense.at(-1, 0), None);
+        assert_eq!(dense.at(2, 0), None);
+        assert_eq!(dense.at(0, 2), None);
+        assert_eq!(sparse.at(-1, 0), Some(&'.'));
+
+        *dense.at_mut(0, 0).unwrap() = '#';
+        *sparse.at_mut(0, 0).unwrap() = '#';
+        *sparse.at_mut(5, 0).unwrap() = '#';
+        assert_eq!(count_on(&dense), 4);
+        assert_eq!(count_on(&sparse), 5);
+        assert_eq!(sparse.bounding_box().unwrap().width(), 6);
+    }
+}